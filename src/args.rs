@@ -1,12 +1,91 @@
-use clap::{App, AppSettings, Arg, ArgMatches, ErrorKind, SubCommand};
-use std::{env, process};
+use clap::{App, AppSettings, Arg, ArgMatches, ErrorKind, Shell, SubCommand};
+use std::{
+    env,
+    io::{self, Write},
+    process,
+};
 
-pub fn parse() -> ArgMatches<'static> {
-    let footnote = "Cargo commands (-x) are always executed before shell commands (-s). You can use the `-- command` style instead, note you'll need to use full commands, it won't prefix `cargo` for you. For select cargo subcommands (bench, build, clippy, run, test), you can use them directly without `--`.\n\nBy default, the workspace directories of your project and all local dependencies are watched, except for the target/ and .git/ folders. Your .ignore and .gitignore files are used to filter paths.".to_owned();
+use crate::completions;
+
+/// The arguments cargo-watch was invoked with.
+///
+/// Allows invocation of cargo-watch with both `cargo-watch watch ARGS`
+/// (as invoked by cargo) and `cargo-watch ARGS`.
+pub fn get_args() -> Vec<String> {
+    let mut args: Vec<String> = env::args().collect();
+    if args.get(1).map(|s| s.as_str()) == Some("watch") {
+        args.remove(1);
+    }
+    args
+}
+
+/// Parses the arguments, printing help or an error and exiting if needed.
+pub fn parse(args: &[String]) -> ArgMatches<'static> {
+    match try_parse(args) {
+        Ok(matches) => {
+            if let Some(sub) = matches.subcommand_matches("completions") {
+                let result = match (sub.value_of("values"), sub.value_of("shell")) {
+                    (Some(kind), _) => completions::print_values(kind),
+                    (None, shell) => {
+                        completions::write(shell.unwrap().parse().unwrap(), &mut io::stdout())
+                            .map_err(|err| err.to_string())
+                    }
+                };
+                if let Err(err) = result {
+                    eprintln!("{}", err);
+                    process::exit(1);
+                }
+                process::exit(0);
+            }
+
+            matches
+        }
+        Err(err) => {
+            match err.kind {
+                ErrorKind::HelpDisplayed => {
+                    println!("{}", err);
+                    process::exit(0);
+                }
+
+                ErrorKind::VersionDisplayed => {
+                    // Unlike HelpDisplayed, VersionDisplayed emits the output
+                    // by itself (clap-rs/clap#1390). It also does so without a
+                    // trailing newline, so we print one ourselves.
+                    println!();
+                    process::exit(0);
+                }
+
+                _ => err.exit(),
+            }
+        }
+    }
+}
+
+/// Parses the arguments, returning errors instead of exiting.
+pub fn try_parse(args: &[String]) -> clap::Result<ArgMatches<'static>> {
+    let footnote = footnote();
+    app(&footnote).get_matches_from_safe_borrow(args)
+}
+
+/// Writes completions for the shell, as clap generates them from the arguments.
+///
+/// The command is completed as `cargo-watch`, which is what cargo's own
+/// completions hand over to for `cargo watch`.
+pub fn completions(shell: Shell, out: &mut impl Write) {
+    let footnote = footnote();
+    app(&footnote).gen_completions_to("cargo-watch", shell, out);
+}
+
+fn footnote() -> String {
+    let footnote = "Cargo commands (-x) are always executed before shell commands (-s). You can use the `-- command` style instead, note you'll need to use full commands, it won't prefix `cargo` for you. For select cargo subcommands (bench, build, clippy, run, test), you can use them directly without `--`.\n\nBy default, the workspace directories of your project and all local dependencies are watched, except for the target/ and .git/ folders. Your .ignore and .gitignore files are used to filter paths.\n\nDefaults for these options can be set in a .cargo-watch.toml file or a [package.metadata.watch] or [workspace.metadata.watch] table in Cargo.toml, using the long option names as keys, or in CARGO_WATCH_* environment variables named after the long options (e.g. CARGO_WATCH_POLL=1). Options given on the command line take precedence over the environment, which takes precedence over config.".to_owned();
 
     #[cfg(windows)] let footnote = format!("{}\n\nOn Windows, patterns given to -i have forward slashes (/) automatically converted to backward ones (\\) to ease command portability.", footnote);
 
-    let mut app = App::new(env!("CARGO_PKG_NAME"))
+    footnote
+}
+
+fn app(footnote: &str) -> App<'static, '_> {
+    App::new(env!("CARGO_PKG_NAME"))
         .bin_name("cargo watch")
         .author(env!("CARGO_PKG_HOMEPAGE"))
         .about(env!("CARGO_PKG_DESCRIPTION"))
@@ -63,9 +142,51 @@ pub fn parse() -> ArgMatches<'static> {
         .arg(
             Arg::with_name("packages:all")
                 .long("all")
+                .alias("workspace")
                 .conflicts_with("packages:one")
-                .hidden(true)
-                .help("Reserved for workspace support"),
+                .help("Watch all workspace members, and pass --workspace to cargo commands"),
+        )
+        .arg(
+            Arg::with_name("affected")
+                .long("affected")
+                .conflicts_with_all(&["packages:one", "packages:all"])
+                .help("Run cargo commands only for the workspace members affected by changes"),
+        )
+        .arg(
+            Arg::with_name("dep-info")
+                .long("dep-info")
+                .help("Only run for changes to files that built targets depend on, and to Cargo.toml and Cargo.lock"),
+        )
+        .arg(
+            Arg::with_name("skip-unchanged")
+                .long("skip-unchanged")
+                .help("Ignore changes to files whose contents are the same as before, like from formatters or touch"),
+        )
+        .arg(
+            Arg::with_name("no-git-wait")
+                .long("no-git-wait")
+                .help("Don’t hold runs while git checks out, rebases or stashes files"),
+        )
+        .arg(
+            Arg::with_name("keep-running")
+                .long("keep-running")
+                .help("For `run` commands, build first and only replace the running program if the build succeeds"),
+        )
+        .arg(
+            Arg::with_name("no-keys")
+                .long("no-keys")
+                .help("Don’t read single-key commands (r, c, p, k, q) from the terminal, leaving it to the command"),
+        )
+        .arg(
+            Arg::with_name("keys")
+                .long("keys")
+                .conflicts_with("no-keys")
+                .help("Read single-key commands even for `run` and shell commands, which then get no input"),
+        )
+        .arg(
+            Arg::with_name("control")
+                .long("control")
+                .help("Listen for commands (rerun, pause, resume, status) on a socket in the target directory"),
         )
         .arg(
             Arg::with_name("poll")
@@ -123,6 +244,17 @@ pub fn parse() -> ArgMatches<'static> {
                 .number_of_values(1)
                 .help("Shell command(s) to execute on changes"),
         )
+        .arg(
+            Arg::with_name("on")
+                .long("on")
+                .takes_value(true)
+                .value_name("pattern=cmd")
+                .multiple(true)
+                .empty_values(false)
+                .min_values(1)
+                .number_of_values(1)
+                .help("Run this command instead of the others when a changed path matches the glob pattern, e.g. `--on '*.sql=cargo test'`"),
+        )
         .arg(
             Arg::with_name("delay")
                 .short("d")
@@ -144,6 +276,30 @@ pub fn parse() -> ArgMatches<'static> {
                 .number_of_values(1)
                 .help("Ignore a glob/gitignore-style pattern"),
         )
+        .arg(
+            Arg::with_name("filter")
+                .short("f")
+                .long("filter")
+                .takes_value(true)
+                .value_name("pattern")
+                .multiple(true)
+                .empty_values(false)
+                .min_values(1)
+                .number_of_values(1)
+                .help("Only run for changes to paths matching a glob pattern"),
+        )
+        .arg(
+            Arg::with_name("exts")
+                .short("e")
+                .long("exts")
+                .takes_value(true)
+                .value_name("extensions")
+                .multiple(true)
+                .empty_values(false)
+                .min_values(1)
+                .number_of_values(1)
+                .help("Only run for changes to files with these extensions, separated by commas"),
+        )
         .arg(
             Arg::with_name("packages:one")
                 .short("p")
@@ -153,8 +309,8 @@ pub fn parse() -> ArgMatches<'static> {
                 .multiple(true)
                 .empty_values(false)
                 .min_values(1)
-                .hidden(true)
-                .help("Reserved for workspace support"),
+                .number_of_values(1)
+                .help("Watch only this package and its local dependencies, and pass -p to cargo commands"),
         )
         .arg(
             Arg::with_name("watch")
@@ -186,13 +342,13 @@ pub fn parse() -> ArgMatches<'static> {
         )
         .arg(
             Arg::with_name("notif")
-                .help("Send a desktop notification when watchexec notices a change (experimental, behaviour may change)")
+                .help("Send a desktop notification when the commands finish (experimental, behaviour may change)")
                 .short("N")
                 .long("notify")
         )
         .arg(
             Arg::with_name("env-vars")
-                .help("Set environment variables for the command")
+                .help("Set environment variables for the commands. Prefix with `cmd:` to set one only for commands starting with that word, e.g. `run:RUST_LOG=debug`")
                 .short("E")
                 .long("env")
                 .takes_value(true)
@@ -203,7 +359,7 @@ pub fn parse() -> ArgMatches<'static> {
         )
         .arg(
             Arg::with_name("env-files")
-                .help("Set environment variables from a .env file")
+                .help("Set environment variables from a .env file, reloaded and rerunning the commands when it changes")
                 .long("env-file")
                 .takes_value(true)
                 .multiple(true)
@@ -211,9 +367,17 @@ pub fn parse() -> ArgMatches<'static> {
                 .min_values(1)
                 .number_of_values(1)
         )
+        .arg(
+            Arg::with_name("changed-paths-file")
+                .long("changed-paths-file")
+                .takes_value(true)
+                .value_name("path")
+                .empty_values(false)
+                .help("Before each run, write the changed paths to this file, one per line, and set CARGO_WATCH_PATHS_FILE to it")
+        )
         .arg(
             Arg::with_name("env-changes")
-                .help("EXPERIMENTAL: Set environment variables for modified files")
+                .help("Deprecated: also set the WATCHEXEC_*_PATH variables listing changed paths")
                 .long("experimental--env-changes")
                 .hidden(true)
         )
@@ -229,6 +393,31 @@ pub fn parse() -> ArgMatches<'static> {
                 .short("L")
                 .takes_value(true)
         )
+        .arg(
+            Arg::with_name("profile")
+                .long("profile")
+                .takes_value(true)
+                .value_name("name")
+                .empty_values(false)
+                .help("Use the named profile from the config file"),
+        )
+        .arg(
+            Arg::with_name("message-format")
+                .long("message-format")
+                .takes_value(true)
+                .value_name("fmt")
+                .possible_values(&["human", "json"])
+                .default_value("human")
+                .help("How to report changes and runs. `json` prints one JSON event per line"),
+        )
+        .arg(
+            Arg::with_name("message-file")
+                .long("message-file")
+                .takes_value(true)
+                .value_name("path")
+                .empty_values(false)
+                .help("With --message-format json, write events to this file instead of stdout, keeping the usual output"),
+        )
         .arg(
             Arg::with_name("skip-local-deps")
             .help("Don't try to find local dependencies of the current crate and watch their working directories. Only watch the current directory.")
@@ -239,52 +428,39 @@ pub fn parse() -> ArgMatches<'static> {
         .subcommand(special_cargo_subc("check"))
         .subcommand(special_cargo_subc("clippy"))
         .subcommand(special_cargo_subc("test"))
+        .subcommand(
+            SubCommand::with_name("completions")
+                .about("Print completions for a shell")
+                .setting(AppSettings::DisableVersion)
+                .arg(
+                    Arg::with_name("shell")
+                        .required_unless("values")
+                        .possible_values(&Shell::variants()),
+                )
+                .arg(
+                    Arg::with_name("values")
+                        .long("values")
+                        .takes_value(true)
+                        .hidden(true)
+                        .possible_values(&["packages", "features", "bins", "tests", "examples", "benches"])
+                        .help("Print the names to complete for an option, used by the completions themselves"),
+                ),
+        )
         .arg(
             Arg::with_name("cmd:trail")
                 .raw(true)
                 .help("Full command to run. -x and -s will be ignored!"),
         )
-        .after_help(footnote.as_str());
-
-    fn special_cargo_subc(name: &str) -> App {
-        SubCommand::with_name(name)
-            .setting(AppSettings::AllowLeadingHyphen)
-            .setting(AppSettings::DisableHelpFlags)
-            .setting(AppSettings::DisableHelpSubcommand)
-            .setting(AppSettings::DisableVersion)
-            .setting(AppSettings::Hidden)
-            .setting(AppSettings::TrailingVarArg)
-            .arg(Arg::with_name("args").multiple(true))
-    }
-
-    // Allow invocation of cargo-watch with both `cargo-watch watch ARGS`
-    // (as invoked by cargo) and `cargo-watch ARGS`.
-    let mut args: Vec<String> = env::args().collect();
-    if args.get(1).map(|s| s.as_str()) == Some("watch") {
-        args.remove(1);
-    }
-
-    let matches = match app.get_matches_from_safe_borrow(args) {
-        Ok(matches) => matches,
-        Err(err) => {
-            match err.kind {
-                ErrorKind::HelpDisplayed => {
-                    println!("{}", err);
-                    process::exit(0);
-                }
-
-                ErrorKind::VersionDisplayed => {
-                    // Unlike HelpDisplayed, VersionDisplayed emits the output
-                    // by itself (clap-rs/clap#1390). It also does so without a
-                    // trailing newline, so we print one ourselves.
-                    println!();
-                    process::exit(0);
-                }
-
-                _ => app.get_matches(),
-            }
-        }
-    };
+        .after_help(footnote)
+}
 
-    matches
+fn special_cargo_subc(name: &'static str) -> App<'static, 'static> {
+    SubCommand::with_name(name)
+        .setting(AppSettings::AllowLeadingHyphen)
+        .setting(AppSettings::DisableHelpFlags)
+        .setting(AppSettings::DisableHelpSubcommand)
+        .setting(AppSettings::DisableVersion)
+        .setting(AppSettings::Hidden)
+        .setting(AppSettings::TrailingVarArg)
+        .arg(Arg::with_name("args").multiple(true))
 }
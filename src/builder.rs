@@ -0,0 +1,315 @@
+use std::{fmt, path::PathBuf, sync::Arc, time::Duration};
+
+use watchexec::{
+    error::{Error, Result},
+    Shell,
+};
+
+use crate::{
+    args,
+    events::{Reporter, RunObserver},
+    options,
+    watch::CwHandler,
+};
+
+/// Sets up a watch the way `cargo watch` would, without going through its
+/// command line.
+///
+/// Nothing is read from config files or the environment. Paths are relative to
+/// the current directory, which should be in the Cargo project to watch.
+#[derive(Clone, Default)]
+pub struct Builder {
+    watches: Vec<PathBuf>,
+    ignores: Vec<String>,
+    filters: Vec<String>,
+    cargo: Vec<String>,
+    commands: Vec<String>,
+    routes: Vec<(String, String)>,
+    shell: Option<Shell>,
+    features: Option<String>,
+    packages: Vec<String>,
+    all: bool,
+    skip_local_deps: bool,
+    ignore_nothing: bool,
+    no_vcs_ignores: bool,
+    no_dot_ignores: bool,
+    dep_info: bool,
+    skip_unchanged: bool,
+    no_git_wait: bool,
+    delay: Option<Duration>,
+    poll: bool,
+    postpone: bool,
+    clear: bool,
+    no_restart: bool,
+    quiet: bool,
+    observers: Vec<Arc<dyn RunObserver>>,
+}
+
+impl fmt::Debug for Builder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Builder")
+            .field("args", &self.to_args())
+            .field("observers", &self.observers.len())
+            .finish()
+    }
+}
+
+impl Builder {
+    /// Watches the crate and its local dependencies, and runs `cargo check`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Watches a file or folder instead of the crate and its local dependencies.
+    ///
+    /// Can be called several times.
+    pub fn watch(&mut self, path: impl Into<PathBuf>) -> &mut Self {
+        self.watches.push(path.into());
+        self
+    }
+
+    /// Ignores a glob or gitignore-style pattern, on top of `target/`, `.git/`,
+    /// and the project's ignore files.
+    pub fn ignore(&mut self, pattern: impl Into<String>) -> &mut Self {
+        self.ignores.push(pattern.into());
+        self
+    }
+
+    /// Runs only for changes to paths matching a glob pattern. Ignores still
+    /// apply on top.
+    ///
+    /// Can be called several times.
+    pub fn filter(&mut self, pattern: impl Into<String>) -> &mut Self {
+        self.filters.push(pattern.into());
+        self
+    }
+
+    /// Adds a cargo command to run, without the `cargo`, like `"test --lib"`.
+    ///
+    /// Cargo commands run before shell commands, in the order they were added.
+    pub fn cargo(&mut self, command: impl Into<String>) -> &mut Self {
+        self.cargo.push(command.into());
+        self
+    }
+
+    /// Adds a shell command to run.
+    pub fn command(&mut self, command: impl Into<String>) -> &mut Self {
+        self.commands.push(command.into());
+        self
+    }
+
+    /// Runs a command instead of the others when a changed path matches the
+    /// glob pattern. Commands starting with `cargo` are treated like cargo
+    /// commands, others are given to the shell.
+    ///
+    /// Can be called several times.
+    pub fn on(&mut self, pattern: impl Into<String>, command: impl Into<String>) -> &mut Self {
+        self.routes.push((pattern.into(), command.into()));
+        self
+    }
+
+    /// The shell to run commands with, instead of the platform's default.
+    pub fn shell(&mut self, shell: Shell) -> &mut Self {
+        self.shell = Some(shell);
+        self
+    }
+
+    /// Features to pass to the cargo commands which take them.
+    pub fn features(&mut self, features: impl Into<String>) -> &mut Self {
+        self.features = Some(features.into());
+        self
+    }
+
+    /// Watches only this workspace member and its local dependencies, and
+    /// passes `-p` to the cargo commands.
+    ///
+    /// Can be called several times.
+    pub fn package(&mut self, spec: impl Into<String>) -> &mut Self {
+        self.packages.push(spec.into());
+        self
+    }
+
+    /// Watches all workspace members, and passes `--workspace` to the cargo commands.
+    pub fn all(&mut self, all: bool) -> &mut Self {
+        self.all = all;
+        self
+    }
+
+    /// Watches only the current directory, not local dependencies.
+    pub fn skip_local_deps(&mut self, skip: bool) -> &mut Self {
+        self.skip_local_deps = skip;
+        self
+    }
+
+    /// Ignores nothing, not even `target/` and `.git/`.
+    pub fn ignore_nothing(&mut self, ignore_nothing: bool) -> &mut Self {
+        self.ignore_nothing = ignore_nothing;
+        self
+    }
+
+    /// Doesn't use `.gitignore` files.
+    pub fn no_vcs_ignores(&mut self, no_vcs_ignores: bool) -> &mut Self {
+        self.no_vcs_ignores = no_vcs_ignores;
+        self
+    }
+
+    /// Doesn't use `.ignore` files.
+    pub fn no_dot_ignores(&mut self, no_dot_ignores: bool) -> &mut Self {
+        self.no_dot_ignores = no_dot_ignores;
+        self
+    }
+
+    /// Runs only for changes to files which built targets depend on, as listed
+    /// in cargo's dep-info files, and to `Cargo.toml` and `Cargo.lock`.
+    pub fn dep_info(&mut self, dep_info: bool) -> &mut Self {
+        self.dep_info = dep_info;
+        self
+    }
+
+    /// Ignores changes to files whose contents are the same as before, as
+    /// when formatters or editors write them back unchanged.
+    pub fn skip_unchanged(&mut self, skip_unchanged: bool) -> &mut Self {
+        self.skip_unchanged = skip_unchanged;
+        self
+    }
+
+    /// Doesn't hold runs while git rewrites files, as for checkouts, rebases
+    /// and stashes.
+    pub fn no_git_wait(&mut self, no_git_wait: bool) -> &mut Self {
+        self.no_git_wait = no_git_wait;
+        self
+    }
+
+    /// How long to wait for changes to settle before running, 0.5s by default.
+    pub fn delay(&mut self, delay: Duration) -> &mut Self {
+        self.delay = Some(delay);
+        self
+    }
+
+    /// Polls for changes instead of relying on filesystem events.
+    pub fn poll(&mut self, poll: bool) -> &mut Self {
+        self.poll = poll;
+        self
+    }
+
+    /// Waits for a change before the first run.
+    pub fn postpone(&mut self, postpone: bool) -> &mut Self {
+        self.postpone = postpone;
+        self
+    }
+
+    /// Clears the screen before each run.
+    pub fn clear(&mut self, clear: bool) -> &mut Self {
+        self.clear = clear;
+        self
+    }
+
+    /// Lets running commands finish instead of restarting them on changes.
+    pub fn no_restart(&mut self, no_restart: bool) -> &mut Self {
+        self.no_restart = no_restart;
+        self
+    }
+
+    /// Doesn't print `[Running ...]` and similar lines.
+    pub fn quiet(&mut self, quiet: bool) -> &mut Self {
+        self.quiet = quiet;
+        self
+    }
+
+    /// Adds an observer, to be told about changes, commands and runs.
+    ///
+    /// Can be called several times.
+    pub fn observer(&mut self, observer: Arc<dyn RunObserver>) -> &mut Self {
+        self.observers.push(observer);
+        self
+    }
+
+    /// Finds the project's local dependencies and sets up the handler, which
+    /// can then [`watch`](CwHandler::watch).
+    ///
+    /// Fails if options conflict or don't make sense, like an invalid pattern.
+    pub fn build(&self) -> Result<CwHandler> {
+        let matches =
+            args::try_parse(&self.to_args()).map_err(|err| Error::Generic(err.message))?;
+        let config = options::get_options(&matches)?;
+        let mut reporter = Reporter::human(self.quiet);
+        for observer in &self.observers {
+            reporter.observe(Arc::clone(observer));
+        }
+        CwHandler::new(config, matches, reporter)
+    }
+
+    /// The equivalent command-line arguments, which the rest of cargo-watch
+    /// knows how to deal with.
+    fn to_args(&self) -> Vec<String> {
+        let mut args = vec![String::from("cargo-watch")];
+
+        args.extend(
+            self.watches
+                .iter()
+                .map(|path| format!("--watch={}", path.to_string_lossy())),
+        );
+        args.extend(
+            self.ignores
+                .iter()
+                .map(|pattern| format!("--ignore={pattern}")),
+        );
+        args.extend(
+            self.filters
+                .iter()
+                .map(|pattern| format!("--filter={pattern}")),
+        );
+        args.extend(self.cargo.iter().map(|command| format!("--exec={command}")));
+        args.extend(
+            self.commands
+                .iter()
+                .map(|command| format!("--shell={command}")),
+        );
+        args.extend(
+            self.routes
+                .iter()
+                .map(|(pattern, command)| format!("--on={pattern}={command}")),
+        );
+
+        if let Some(shell) = &self.shell {
+            let name = match shell {
+                Shell::None => "none",
+                Shell::Unix(name) => name.as_str(),
+                Shell::Powershell => "powershell",
+                #[cfg(windows)]
+                Shell::Cmd => "cmd",
+            };
+            args.push(format!("--use-shell={name}"));
+        }
+
+        if let Some(features) = &self.features {
+            args.push(format!("--features={features}"));
+        }
+        args.extend(self.packages.iter().map(|spec| format!("--package={spec}")));
+        if let Some(delay) = self.delay {
+            args.push(format!("--delay={}", delay.as_secs_f64()));
+        }
+
+        for (set, flag) in [
+            (self.all, "--all"),
+            (self.skip_local_deps, "--skip-local-deps"),
+            (self.ignore_nothing, "--ignore-nothing"),
+            (self.no_vcs_ignores, "--no-vcs-ignores"),
+            (self.no_dot_ignores, "--no-dot-ignores"),
+            (self.dep_info, "--dep-info"),
+            (self.skip_unchanged, "--skip-unchanged"),
+            (self.no_git_wait, "--no-git-wait"),
+            (self.poll, "--poll"),
+            (self.postpone, "--postpone"),
+            (self.clear, "--clear"),
+            (self.no_restart, "--no-restart"),
+            (self.quiet, "--quiet"),
+        ] {
+            if set {
+                args.push(flag.into());
+            }
+        }
+
+        args
+    }
+}
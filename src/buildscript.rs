@@ -0,0 +1,223 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use cargo_metadata::Metadata;
+
+/// Where to find the output of the build scripts of local packages.
+#[derive(Clone, Debug)]
+pub struct BuildScripts {
+    target_dir: PathBuf,
+    /// The directory of each local package, by name, as build directories are
+    /// named `<package>-<hash>`.
+    packages: HashMap<String, PathBuf>,
+}
+
+impl BuildScripts {
+    pub fn from_metadata(metadata: &Metadata) -> Self {
+        let packages = metadata
+            .packages
+            .iter()
+            // Remote packages have a source, and their build scripts only
+            // look at their own files.
+            .filter(|pkg| pkg.source.is_none())
+            .filter(|pkg| {
+                pkg.targets
+                    .iter()
+                    .any(|target| target.kind.iter().any(|kind| kind == "custom-build"))
+            })
+            .filter_map(|pkg| {
+                let dir = pkg.manifest_path.parent()?;
+                Some((pkg.name.clone(), dir.as_std_path().to_path_buf()))
+            })
+            .collect();
+
+        Self {
+            target_dir: metadata.target_directory.clone().into_std_path_buf(),
+            packages,
+        }
+    }
+
+    /// Reads what the build scripts that have run so far declared, in the
+    /// `output` files under `target/*/build/*/`. Only paths which exist are
+    /// returned.
+    pub fn declared(&self) -> Declared {
+        let mut declared = Declared::default();
+        if self.packages.is_empty() {
+            return declared;
+        }
+
+        // target/<profile>/build/ and target/<triple>/<profile>/build/
+        let mut build_dirs = Vec::new();
+        for dir in subdirs(&self.target_dir) {
+            build_dirs.push(dir.join("build"));
+            build_dirs.extend(subdirs(&dir).map(|dir| dir.join("build")));
+        }
+
+        for dir in build_dirs.iter().flat_map(|dir| subdirs(dir)) {
+            let package = dir
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.rsplit_once('-'))
+                .and_then(|(package, _hash)| self.packages.get(package));
+            let (package, output) = match (package, fs::read_to_string(dir.join("output"))) {
+                (Some(package), Ok(output)) => (package, output),
+                _ => continue,
+            };
+
+            parse_output(&output, package, &mut declared);
+        }
+
+        declared
+    }
+}
+
+/// What build scripts said they depend on, sorted so it can be compared.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Declared {
+    /// The files and directories from `rerun-if-changed`.
+    pub paths: Vec<PathBuf>,
+    /// The variables from `rerun-if-env-changed`.
+    pub env: Vec<String>,
+}
+
+/// Adds the paths declared with `rerun-if-changed` in a build script's output,
+/// which are relative to its package's directory, and the variables declared
+/// with `rerun-if-env-changed`.
+fn parse_output(output: &str, package: &Path, declared: &mut Declared) {
+    for line in output.lines() {
+        let instruction = match line
+            .strip_prefix("cargo::")
+            .or_else(|| line.strip_prefix("cargo:"))
+        {
+            Some(instruction) => instruction,
+            None => continue,
+        };
+
+        if let Some(path) = instruction.strip_prefix("rerun-if-changed=") {
+            if let Ok(path) = package.join(path).canonicalize() {
+                if let Err(at) = declared.paths.binary_search(&path) {
+                    declared.paths.insert(at, path);
+                }
+            }
+        } else if let Some(name) = instruction.strip_prefix("rerun-if-env-changed=") {
+            let name = name.trim().to_string();
+            if let Err(at) = declared.env.binary_search(&name) {
+                declared.env.insert(at, name);
+            }
+        }
+    }
+}
+
+fn subdirs(dir: &Path) -> impl Iterator<Item = PathBuf> {
+    fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter(|entry| entry.file_type().is_ok_and(|t| t.is_dir()))
+        .map(|entry| entry.path())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh directory for a test, with the given files in it.
+    fn fixture(name: &str, files: &[&str]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "cargo-watch-buildscript-{}-{}",
+            name,
+            std::process::id()
+        ));
+        fs::remove_dir_all(&dir).ok();
+        for file in files {
+            let file = dir.join(file);
+            fs::create_dir_all(file.parent().unwrap()).unwrap();
+            fs::write(file, "").unwrap();
+        }
+        dir.canonicalize().unwrap()
+    }
+
+    #[test]
+    fn parses_rerun_if_changed() {
+        let dir = fixture("parse", &["foo/build.rs", "proto/a.proto"]);
+        let output = "cargo:rerun-if-changed=build.rs\n\
+                      cargo::rerun-if-changed=../proto\n\
+                      cargo:rerun-if-env-changed=PROTOC\n\
+                      cargo:warning=rerun-if-changed=nothing\n\
+                      rerun-if-changed=build.rs\n";
+        let mut declared = Declared::default();
+        parse_output(output, &dir.join("foo"), &mut declared);
+        assert_eq!(
+            declared.paths,
+            vec![dir.join("foo/build.rs"), dir.join("proto")]
+        );
+    }
+
+    #[test]
+    fn parses_rerun_if_env_changed() {
+        let dir = fixture("env", &["foo/build.rs"]);
+        let output = "cargo:rerun-if-env-changed=PROTOC\n\
+                      cargo::rerun-if-env-changed=FOO_SYS_STATIC\n\
+                      cargo:rerun-if-env-changed=PROTOC\n\
+                      cargo:warning=rerun-if-env-changed=NOTHING\n";
+        let mut declared = Declared::default();
+        parse_output(output, &dir.join("foo"), &mut declared);
+        assert_eq!(declared.env, vec!["FOO_SYS_STATIC", "PROTOC"]);
+        assert!(declared.paths.is_empty());
+    }
+
+    #[test]
+    fn skips_missing_and_repeated_paths() {
+        let dir = fixture("missing", &["foo/build.rs"]);
+        let output = "cargo:rerun-if-changed=build.rs\n\
+                      cargo:rerun-if-changed=missing.txt\n\
+                      cargo:rerun-if-changed=./build.rs\n";
+        let mut declared = Declared::default();
+        parse_output(output, &dir.join("foo"), &mut declared);
+        assert_eq!(declared.paths, vec![dir.join("foo/build.rs")]);
+    }
+
+    #[test]
+    fn reads_output_of_local_packages() {
+        let dir = fixture(
+            "declared",
+            &[
+                "foo/build.rs",
+                "shared/schema.json",
+                "target/debug/build/foo-0123abcd/output",
+                "target/x86_64-unknown-linux-gnu/debug/build/foo-4567cdef/output",
+                "target/debug/build/remote-89abcdef/output",
+            ],
+        );
+        let target = dir.join("target");
+        fs::write(
+            target.join("debug/build/foo-0123abcd/output"),
+            "cargo:rerun-if-changed=build.rs\ncargo:rerun-if-env-changed=FOO_DIR\n",
+        )
+        .unwrap();
+        fs::write(
+            target.join("x86_64-unknown-linux-gnu/debug/build/foo-4567cdef/output"),
+            "cargo:rerun-if-changed=../shared/schema.json\n",
+        )
+        .unwrap();
+        fs::write(
+            target.join("debug/build/remote-89abcdef/output"),
+            "cargo:rerun-if-changed=/\n",
+        )
+        .unwrap();
+
+        let scripts = BuildScripts {
+            target_dir: target,
+            packages: HashMap::from([("foo".to_string(), dir.join("foo"))]),
+        };
+        let declared = scripts.declared();
+        assert_eq!(
+            declared.paths,
+            vec![dir.join("foo/build.rs"), dir.join("shared/schema.json")]
+        );
+        assert_eq!(declared.env, vec!["FOO_DIR"]);
+    }
+}
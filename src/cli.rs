@@ -0,0 +1,128 @@
+use std::{path::PathBuf, sync::Arc};
+
+use camino::Utf8PathBuf;
+use clap::ErrorKind;
+use stderrlog::Timestamp;
+use watchexec::error::{Error, Result};
+
+#[cfg(unix)]
+use crate::control;
+use crate::{
+    args, config, environment,
+    events::Reporter,
+    keys, options, process, root,
+    watch::{CwHandler, Notifier},
+};
+
+/// Runs `cargo watch` with the process's arguments, exiting on bad ones.
+pub fn main() -> Result<()> {
+    let args = args::get_args();
+    let matches = args::parse(&args);
+    let mut origins = config::Origins::default();
+    let (args, matches) = config::apply_env(&args, matches, &mut origins);
+
+    let debug = matches.is_present("log:debug");
+    let info = matches.is_present("log:info");
+    let quiet = matches.is_present("log:quiet");
+    let testing = matches.is_present("once");
+
+    stderrlog::new()
+        .quiet(quiet)
+        .show_module_names(debug)
+        .verbosity(if debug {
+            3
+        } else if info {
+            2
+        } else {
+            1
+        })
+        .timestamp(if testing {
+            Timestamp::Off
+        } else {
+            Timestamp::Millisecond
+        })
+        .init()
+        .unwrap();
+
+    root::change_dir(
+        matches
+            .value_of("workdir")
+            .map(Utf8PathBuf::from)
+            .unwrap_or_else(root::project_root),
+    );
+
+    let matches = config::apply(&args, matches, &mut origins);
+    origins.log(&matches);
+
+    let opts = options::get_options(&matches).unwrap_or_else(|err| invalid(&err));
+    let mut reporter = match matches.value_of("message-format") {
+        Some("json") => Reporter::json(quiet, matches.value_of("message-file").map(PathBuf::from))
+            .unwrap_or_else(|e| {
+                clap::Error::with_description(
+                    &format!("Failed to open message file: {e}"),
+                    ErrorKind::Io,
+                )
+                .exit()
+            }),
+        _ => Reporter::human(quiet),
+    };
+    if matches.is_present("notif") {
+        reporter.observe(Arc::new(Notifier));
+    }
+    let control = matches.is_present("control");
+    let keys = !testing
+        && keys::available()
+        && (matches.is_present("keys")
+            || !(matches.is_present("no-keys") || options::may_read_stdin(&matches)));
+    let env_files: Vec<PathBuf> = matches
+        .values_of("env-files")
+        .into_iter()
+        .flatten()
+        .map(PathBuf::from)
+        .collect();
+    let handler = match CwHandler::new(opts, matches, reporter) {
+        Ok(handler) => Arc::new(handler),
+        Err(Error::Generic(err)) => invalid(&err),
+        Err(err) => return Err(err),
+    };
+    if !env_files.is_empty() {
+        environment::watch_files(Arc::clone(&handler), env_files);
+    }
+
+    let _terminal = if keys {
+        keys::listen(Arc::clone(&handler))
+    } else {
+        None
+    };
+
+    #[cfg(unix)]
+    let _socket = if control {
+        match control::listen(Arc::clone(&handler)) {
+            Ok(socket) => {
+                if !quiet {
+                    eprintln!("[Listening for commands on {}]", socket.path().display());
+                }
+                Some(socket)
+            }
+            Err(err) => {
+                eprintln!("Failed to open the control socket: {err}");
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    #[cfg(not(unix))]
+    if control {
+        eprintln!("The control socket is only supported on Unix");
+    }
+
+    process::signals::install();
+    handler.watch()
+}
+
+/// Prints the error like clap does for bad arguments, and exits.
+fn invalid(err: &str) -> ! {
+    clap::Error::with_description(err, ErrorKind::ValueValidation).exit()
+}
@@ -0,0 +1,103 @@
+use std::io::{self, Write};
+
+use clap::Shell;
+
+use crate::args;
+
+/// Options whose values come from the workspace, and what to ask
+/// `cargo-watch completions --values` for.
+const FROM_METADATA: &[(&[&str], &str)] = &[
+    (&["-p", "--package"], "packages"),
+    (&["--features"], "features"),
+    (&["--bin"], "bins"),
+    (&["--test"], "tests"),
+    (&["--example"], "examples"),
+    (&["--bench"], "benches"),
+];
+
+/// Writes completions for the shell.
+///
+/// For Bash, Fish and Zsh these also complete package, feature and target
+/// names, including after `--bin` and the like in cargo commands such as
+/// `cargo watch run --bin`. Those are looked up when completing, so they follow
+/// the workspace as it changes.
+pub fn write(shell: Shell, out: &mut impl Write) -> io::Result<()> {
+    let mut script = Vec::new();
+    args::completions(shell, &mut script);
+    let script = String::from_utf8_lossy(&script).into_owned();
+
+    let script = match shell {
+        Shell::Bash => bash(script),
+        Shell::Fish => fish(script),
+        Shell::Zsh => zsh(script),
+        Shell::PowerShell | Shell::Elvish => script,
+    };
+    out.write_all(script.as_bytes())
+}
+
+/// Prints the names to complete, one per line.
+pub fn print_values(kind: &str) -> Result<(), String> {
+    let mut stdout = io::stdout().lock();
+    for value in crate::options::completion_values(kind)? {
+        writeln!(stdout, "{value}").map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+fn lookup(kind: &str) -> String {
+    format!("cargo-watch completions --values {kind} 2>/dev/null")
+}
+
+/// Adds text after the first occurrence of the anchor, which is left as is if
+/// clap stops generating it.
+fn insert_after(script: String, anchor: &str, text: &str) -> String {
+    script.replacen(anchor, &format!("{anchor}{text}"), 1)
+}
+
+fn bash(script: String) -> String {
+    let mut cases = String::from("    case \"${prev}\" in\n");
+    for (options, kind) in FROM_METADATA {
+        cases.push_str(&format!(
+            "        {})\n            COMPREPLY=($(compgen -W \"$({})\" -- \"${{cur}}\"))\n            return 0\n            ;;\n",
+            options.join("|"),
+            lookup(kind)
+        ));
+    }
+    cases.push_str("    esac\n");
+
+    insert_after(script, "    prev=\"${COMP_WORDS[COMP_CWORD-1]}\"\n", &cases)
+}
+
+fn zsh(script: String) -> String {
+    let helper = format!(
+        "\n_cargo-watch_values() {{\n    local -a values\n    values=(${{(f)\"$({})\"}})\n    compadd -a values\n}}\n",
+        lookup("$1")
+    );
+
+    let mut cases = String::from("\n    case $words[CURRENT-1] in\n");
+    for (options, kind) in FROM_METADATA {
+        cases.push_str(&format!(
+            "        ({})\n            _cargo-watch_values {}\n            return\n            ;;\n",
+            options.join("|"),
+            kind
+        ));
+    }
+    cases.push_str("    esac\n");
+
+    let script = insert_after(script, "autoload -U is-at-least\n", &helper);
+    insert_after(script, "    local ret=1\n", &cases)
+}
+
+fn fish(mut script: String) -> String {
+    for (options, kind) in FROM_METADATA {
+        script.push_str("complete -c cargo-watch");
+        for option in *options {
+            match option.strip_prefix("--") {
+                Some(long) => script.push_str(&format!(" -l {long}")),
+                None => script.push_str(&format!(" -s {}", &option[1..])),
+            }
+        }
+        script.push_str(&format!(" -xa \"({})\"\n", lookup(kind)));
+    }
+    script
+}
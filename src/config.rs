@@ -0,0 +1,671 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    env, fs,
+    io::ErrorKind as IoErrorKind,
+    path::Path,
+};
+
+use cargo_metadata::MetadataCommand;
+use clap::{ArgMatches, Error, ErrorKind};
+use log::debug;
+use serde::Deserialize;
+
+use crate::args;
+
+pub const CONFIG_FILE: &str = ".cargo-watch.toml";
+
+/// Options which add to the command-line instead of being overridden by it.
+const ACCUMULATE: &[&str] = &["env-vars", "env-files", "ignore"];
+
+/// Groups of options which can't be used together, so setting one on the
+/// command-line overrides the others from the environment or config.
+const EXCLUSIVE: &[&[&str]] = &[
+    &["packages:one", "packages:all", "affected"],
+    &["keys", "no-keys"],
+];
+
+/// Options which give the commands to run: any of them replaces all the others
+/// from lower-priority places.
+const COMMANDS: &[&str] = &["cmd:cargo", "cmd:shell", "on"];
+
+/// Environment variables which set options are named after the long flag with
+/// this prefix, like `CARGO_WATCH_SKIP_LOCAL_DEPS` for `--skip-local-deps`.
+pub const ENV_PREFIX: &str = "CARGO_WATCH_";
+
+#[derive(Clone, Copy, Debug)]
+enum EnvValue {
+    /// A switch, set with `1`, `true`, `yes` or `on`.
+    Flag,
+    /// The value of the option.
+    One,
+    /// Several values, split on the platform's path list separator.
+    Paths,
+    /// Several values, split on commas.
+    List,
+    /// Several values, one per line or separated by semicolons.
+    Lines,
+}
+
+/// Every option which can be set from the environment: the option's id, the
+/// name of the variable without the prefix, and how its value is read.
+const ENV_OPTIONS: &[(&str, &str, EnvValue)] = &[
+    ("cmd:cargo", "EXEC", EnvValue::One),
+    ("cmd:shell", "SHELL", EnvValue::One),
+    ("on", "ON", EnvValue::One),
+    ("features", "FEATURES", EnvValue::One),
+    ("packages:one", "PACKAGE", EnvValue::List),
+    ("packages:all", "ALL", EnvValue::Flag),
+    ("affected", "AFFECTED", EnvValue::Flag),
+    ("watch", "WATCH", EnvValue::Paths),
+    ("ignore", "IGNORE", EnvValue::Paths),
+    ("filter", "FILTER", EnvValue::Paths),
+    ("exts", "EXTS", EnvValue::One),
+    ("skip-local-deps", "SKIP_LOCAL_DEPS", EnvValue::Flag),
+    ("ignore-nothing", "IGNORE_NOTHING", EnvValue::Flag),
+    ("no-vcs-ignores", "NO_VCS_IGNORES", EnvValue::Flag),
+    ("no-dot-ignores", "NO_DOT_IGNORES", EnvValue::Flag),
+    ("dep-info", "DEP_INFO", EnvValue::Flag),
+    ("skip-unchanged", "SKIP_UNCHANGED", EnvValue::Flag),
+    ("no-git-wait", "NO_GIT_WAIT", EnvValue::Flag),
+    ("env-vars", "ENV", EnvValue::Lines),
+    ("env-files", "ENV_FILE", EnvValue::Paths),
+    ("changed-paths-file", "CHANGED_PATHS_FILE", EnvValue::One),
+    ("env-changes", "EXPERIMENTAL_ENV_CHANGES", EnvValue::Flag),
+    ("rust-backtrace", "RUST_BACKTRACE", EnvValue::One),
+    ("rust-log", "RUST_LOG", EnvValue::One),
+    ("delay", "DELAY", EnvValue::One),
+    ("clear", "CLEAR", EnvValue::Flag),
+    ("notif", "NOTIFY", EnvValue::Flag),
+    ("poll", "POLL", EnvValue::Flag),
+    ("postpone", "POSTPONE", EnvValue::Flag),
+    ("no-restart", "NO_RESTART", EnvValue::Flag),
+    ("keep-running", "KEEP_RUNNING", EnvValue::Flag),
+    ("no-keys", "NO_KEYS", EnvValue::Flag),
+    ("keys", "KEYS", EnvValue::Flag),
+    ("control", "CONTROL", EnvValue::Flag),
+    ("watch-when-idle", "WATCH_WHEN_IDLE", EnvValue::Flag),
+    ("no-process-group", "NO_PROCESS_GROUP", EnvValue::Flag),
+    ("use-shell", "USE_SHELL", EnvValue::One),
+    ("workdir", "WORKDIR", EnvValue::One),
+    ("profile", "PROFILE", EnvValue::One),
+    ("message-format", "MESSAGE_FORMAT", EnvValue::One),
+    ("message-file", "MESSAGE_FILE", EnvValue::One),
+    ("log:debug", "DEBUG", EnvValue::Flag),
+    ("log:info", "WHY", EnvValue::Flag),
+    ("log:quiet", "QUIET", EnvValue::Flag),
+];
+
+/// Where the values of options came from, for `--debug`.
+#[derive(Clone, Debug, Default)]
+pub struct Origins(BTreeMap<&'static str, Vec<String>>);
+
+impl Origins {
+    fn add(&mut self, id: &'static str, origin: impl Into<String>) {
+        let origin = origin.into();
+        let origins = self.0.entry(id).or_default();
+        if !origins.contains(&origin) {
+            origins.push(origin);
+        }
+    }
+
+    /// Logs each option which has a value, and where it came from.
+    pub fn log(&self, matches: &ArgMatches) {
+        for (id, name, kind) in ENV_OPTIONS {
+            if !matches.is_present(id) {
+                continue;
+            }
+
+            let flag = env_flag(name);
+            let origin = match self.0.get(id) {
+                Some(origins) => origins.join(", "),
+                None if matches.occurrences_of(id) == 0 => "default".into(),
+                None => "command line".into(),
+            };
+            let values: Vec<&str> = matches.values_of(id).into_iter().flatten().collect();
+            match (kind, values.as_slice()) {
+                (EnvValue::Flag, _) => debug!("Option {} from {}", flag, origin),
+                (_, [value]) => debug!("Option {} = {:?} from {}", flag, value, origin),
+                (_, values) => debug!("Option {} = {:?} from {}", flag, values, origin),
+            }
+        }
+    }
+}
+
+/// Defaults for cargo-watch options, as found in a `.cargo-watch.toml` file or
+/// in the `[package.metadata.watch]` or `[workspace.metadata.watch]` tables.
+///
+/// Keys are named after the long command-line flags. Named profiles are tables
+/// of the same shape under `profile`, selected with `--profile`.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct WatchConfig {
+    pub exec: Option<OneOrMany>,
+    pub shell: Option<OneOrMany>,
+    pub on: Option<OneOrMany>,
+    pub features: Option<String>,
+    pub package: Option<OneOrMany>,
+    pub all: Option<bool>,
+    pub affected: Option<bool>,
+    pub watch: Option<OneOrMany>,
+    pub ignore: Option<OneOrMany>,
+    pub filter: Option<OneOrMany>,
+    pub exts: Option<OneOrMany>,
+    pub skip_local_deps: Option<bool>,
+    pub ignore_nothing: Option<bool>,
+    pub no_vcs_ignores: Option<bool>,
+    pub no_dot_ignores: Option<bool>,
+    pub dep_info: Option<bool>,
+    pub skip_unchanged: Option<bool>,
+    pub no_git_wait: Option<bool>,
+    pub env: Option<BTreeMap<String, String>>,
+    pub env_file: Option<OneOrMany>,
+    pub changed_paths_file: Option<String>,
+    pub rust_backtrace: Option<String>,
+    pub rust_log: Option<String>,
+    pub delay: Option<f32>,
+    pub clear: Option<bool>,
+    pub notify: Option<bool>,
+    pub poll: Option<bool>,
+    pub postpone: Option<bool>,
+    pub no_restart: Option<bool>,
+    pub keep_running: Option<bool>,
+    pub no_keys: Option<bool>,
+    pub keys: Option<bool>,
+    pub control: Option<bool>,
+    pub watch_when_idle: Option<bool>,
+    pub no_process_group: Option<bool>,
+    pub use_shell: Option<String>,
+    pub message_format: Option<String>,
+    pub message_file: Option<String>,
+    pub profile: BTreeMap<String, WatchConfig>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(untagged)]
+pub enum OneOrMany {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl OneOrMany {
+    fn as_slice(&self) -> &[String] {
+        match self {
+            Self::One(one) => std::slice::from_ref(one),
+            Self::Many(many) => many,
+        }
+    }
+}
+
+/// A set of defaults from one place, as command-line arguments keyed by option name.
+struct Layer {
+    origin: String,
+    args: Vec<(&'static str, Vec<String>)>,
+}
+
+impl WatchConfig {
+    fn to_args(&self) -> Vec<(&'static str, Vec<String>)> {
+        let mut args = Vec::new();
+
+        push_values(&mut args, "cmd:cargo", "--exec", self.exec.as_ref());
+        push_values(&mut args, "cmd:shell", "--shell", self.shell.as_ref());
+        push_values(&mut args, "on", "--on", self.on.as_ref());
+        push_value(&mut args, "features", "--features", self.features.as_ref());
+        push_values(
+            &mut args,
+            "packages:one",
+            "--package",
+            self.package.as_ref(),
+        );
+        if self.all == Some(true) {
+            args.push(("packages:all", vec!["--all".into()]));
+        }
+        push_flag(&mut args, "affected", self.affected);
+        push_values(&mut args, "watch", "--watch", self.watch.as_ref());
+        push_values(&mut args, "ignore", "--ignore", self.ignore.as_ref());
+        push_values(&mut args, "filter", "--filter", self.filter.as_ref());
+        push_values(&mut args, "exts", "--exts", self.exts.as_ref());
+        push_flag(&mut args, "skip-local-deps", self.skip_local_deps);
+        push_flag(&mut args, "ignore-nothing", self.ignore_nothing);
+        push_flag(&mut args, "no-vcs-ignores", self.no_vcs_ignores);
+        push_flag(&mut args, "no-dot-ignores", self.no_dot_ignores);
+        push_flag(&mut args, "dep-info", self.dep_info);
+        push_flag(&mut args, "skip-unchanged", self.skip_unchanged);
+        push_flag(&mut args, "no-git-wait", self.no_git_wait);
+        if let Some(env) = &self.env {
+            for (key, value) in env {
+                args.push(("env-vars", vec![format!("--env={key}={value}")]));
+            }
+        }
+        push_values(&mut args, "env-files", "--env-file", self.env_file.as_ref());
+        push_value(
+            &mut args,
+            "changed-paths-file",
+            "--changed-paths-file",
+            self.changed_paths_file.as_ref(),
+        );
+        if let Some(b) = &self.rust_backtrace {
+            args.push(("rust-backtrace", vec!["-B".into(), b.clone()]));
+        }
+        if let Some(l) = &self.rust_log {
+            args.push(("rust-log", vec!["-L".into(), l.clone()]));
+        }
+        push_value(&mut args, "delay", "--delay", self.delay.as_ref());
+        push_flag(&mut args, "clear", self.clear);
+        if self.notify == Some(true) {
+            args.push(("notif", vec!["--notify".into()]));
+        }
+        push_flag(&mut args, "poll", self.poll);
+        push_flag(&mut args, "postpone", self.postpone);
+        push_flag(&mut args, "no-restart", self.no_restart);
+        push_flag(&mut args, "keep-running", self.keep_running);
+        push_flag(&mut args, "no-keys", self.no_keys);
+        push_flag(&mut args, "keys", self.keys);
+        push_flag(&mut args, "control", self.control);
+        push_flag(&mut args, "watch-when-idle", self.watch_when_idle);
+        push_flag(&mut args, "no-process-group", self.no_process_group);
+        push_value(
+            &mut args,
+            "use-shell",
+            "--use-shell",
+            self.use_shell.as_ref(),
+        );
+        push_value(
+            &mut args,
+            "message-format",
+            "--message-format",
+            self.message_format.as_ref(),
+        );
+        push_value(
+            &mut args,
+            "message-file",
+            "--message-file",
+            self.message_file.as_ref(),
+        );
+
+        args
+    }
+}
+
+fn push_flag(args: &mut Vec<(&'static str, Vec<String>)>, id: &'static str, value: Option<bool>) {
+    if value == Some(true) {
+        args.push((id, vec![format!("--{id}")]));
+    }
+}
+
+fn push_value<T: ToString>(
+    args: &mut Vec<(&'static str, Vec<String>)>,
+    id: &'static str,
+    flag: &str,
+    value: Option<&T>,
+) {
+    if let Some(value) = value {
+        args.push((id, vec![format!("{flag}={}", value.to_string())]));
+    }
+}
+
+fn push_values(
+    args: &mut Vec<(&'static str, Vec<String>)>,
+    id: &'static str,
+    flag: &str,
+    values: Option<&OneOrMany>,
+) {
+    for value in values.map_or(&[][..], OneOrMany::as_slice) {
+        args.push((id, vec![format!("{flag}={value}")]));
+    }
+}
+
+fn read_file(path: &Path) -> Option<WatchConfig> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(err) if err.kind() == IoErrorKind::NotFound => return None,
+        Err(err) => Error::with_description(
+            &format!("Failed to read config file {path:?}: {err}"),
+            ErrorKind::Io,
+        )
+        .exit(),
+    };
+
+    Some(toml::from_str(&content).unwrap_or_else(|err| {
+        Error::with_description(
+            &format!("Malformed config file {path:?}: {err}"),
+            ErrorKind::InvalidValue,
+        )
+        .exit()
+    }))
+}
+
+fn read_metadata(metadata: &serde_json::Value, origin: &str) -> Option<WatchConfig> {
+    let table = metadata.get("watch")?;
+    Some(serde_json::from_value(table.clone()).unwrap_or_else(|err| {
+        Error::with_description(
+            &format!("Malformed config in {origin}: {err}"),
+            ErrorKind::InvalidValue,
+        )
+        .exit()
+    }))
+}
+
+/// Config sources found so far, with the selected profile's tables kept apart.
+#[derive(Default)]
+struct Found {
+    configs: Vec<(String, WatchConfig)>,
+    profiles: Vec<(String, WatchConfig)>,
+}
+
+impl Found {
+    /// Adds a source, taking out the selected profile's table.
+    fn push(&mut self, origin: String, mut config: WatchConfig, profile: Option<&str>) {
+        if let Some(name) = config
+            .profile
+            .iter()
+            .find_map(|(name, table)| (!table.profile.is_empty()).then_some(name))
+        {
+            Error::with_description(
+                &format!("Profile '{name}' in {origin} can't have profiles of its own"),
+                ErrorKind::InvalidValue,
+            )
+            .exit()
+        }
+
+        if let Some(name) = profile {
+            if let Some(selected) = config.profile.remove(name) {
+                self.profiles
+                    .push((format!("profile '{name}' in {origin}"), selected));
+            }
+        }
+        self.configs.push((origin, config));
+    }
+
+    /// All sources from highest to lowest priority: the selected profile's
+    /// tables come before every base table, wherever they were found.
+    fn finish(mut self, profile: Option<&str>) -> Vec<(String, WatchConfig)> {
+        if let Some(name) = profile {
+            if self.profiles.is_empty() {
+                Error::with_description(
+                    &format!("Profile '{name}' is not defined in any config"),
+                    ErrorKind::InvalidValue,
+                )
+                .exit()
+            }
+        }
+
+        self.profiles.append(&mut self.configs);
+        self.profiles
+    }
+}
+
+/// Finds all config in the current directory (the crate root), from highest to lowest priority.
+fn load(profile: Option<&str>) -> Vec<(String, WatchConfig)> {
+    let mut found = Found::default();
+    let cwd = env::current_dir().unwrap_or_else(|_| ".".into());
+
+    let local_file = cwd.join(CONFIG_FILE);
+    if let Some(config) = read_file(&local_file) {
+        found.push(local_file.display().to_string(), config, profile);
+    }
+
+    let metadata = match MetadataCommand::new().no_deps().exec() {
+        Ok(metadata) => metadata,
+        Err(err) => {
+            debug!("Not loading config from Cargo.toml: {}", err);
+            return found.finish(profile);
+        }
+    };
+
+    if let Some(package) = metadata
+        .packages
+        .iter()
+        .find(|p| p.manifest_path.parent().map(|p| p.as_std_path()) == Some(cwd.as_path()))
+    {
+        let origin = format!("[package.metadata.watch] in {}", package.manifest_path);
+        if let Some(config) = read_metadata(&package.metadata, &origin) {
+            found.push(origin, config, profile);
+        }
+    }
+
+    let workspace_root = metadata.workspace_root.as_std_path();
+    if workspace_root != cwd {
+        let workspace_file = workspace_root.join(CONFIG_FILE);
+        if let Some(config) = read_file(&workspace_file) {
+            found.push(workspace_file.display().to_string(), config, profile);
+        }
+    }
+
+    let origin = format!(
+        "[workspace.metadata.watch] in {}",
+        metadata.workspace_root.join("Cargo.toml")
+    );
+    if let Some(config) = read_metadata(&metadata.workspace_metadata, &origin) {
+        found.push(origin, config, profile);
+    }
+
+    found.finish(profile)
+}
+
+/// The option, and the others which setting it overrides.
+fn group(id: &'static str) -> Vec<&'static str> {
+    if let Some(group) = exclusive(id) {
+        group.to_vec()
+    } else if COMMANDS.contains(&id) {
+        COMMANDS.to_vec()
+    } else {
+        vec![id]
+    }
+}
+
+fn exclusive(id: &str) -> Option<&'static [&'static str]> {
+    EXCLUSIVE.iter().copied().find(|group| group.contains(&id))
+}
+
+fn is_set_on_cli(matches: &ArgMatches, id: &str) -> bool {
+    if let Some(group) = exclusive(id) {
+        return group.iter().any(|id| matches.occurrences_of(id) > 0);
+    }
+
+    if COMMANDS.contains(&id) {
+        // any command given on the command-line replaces all configured ones
+        return COMMANDS.iter().any(|id| matches.is_present(id))
+            || matches.is_present("cmd:trail")
+            || matches.subcommand_name().is_some();
+    }
+
+    matches.occurrences_of(id) > 0
+}
+
+/// The flag an environment variable stands for.
+fn env_flag(name: &str) -> String {
+    match name {
+        "RUST_BACKTRACE" => "-B".into(),
+        "RUST_LOG" => "-L".into(),
+        "EXPERIMENTAL_ENV_CHANGES" => "--experimental--env-changes".into(),
+        _ => format!("--{}", name.to_ascii_lowercase().replace('_', "-")),
+    }
+}
+
+/// Reads a variable for an option, as arguments.
+fn env_args(var: &str, flag: &str, kind: EnvValue) -> Vec<String> {
+    let value = match env::var(var) {
+        Ok(value) => value,
+        Err(env::VarError::NotPresent) => return Vec::new(),
+        Err(env::VarError::NotUnicode(_)) => Error::with_description(
+            &format!("Environment variable {var} is not valid UTF-8"),
+            ErrorKind::InvalidUtf8,
+        )
+        .exit(),
+    };
+
+    let values: Vec<String> = match kind {
+        EnvValue::Flag => {
+            return match value.to_ascii_lowercase().as_str() {
+                "1" | "true" | "yes" | "on" => vec![flag.into()],
+                "" | "0" | "false" | "no" | "off" => Vec::new(),
+                _ => Error::with_description(
+                    &format!("Environment variable {var} should be true or false, not '{value}'"),
+                    ErrorKind::InvalidValue,
+                )
+                .exit(),
+            }
+        }
+        EnvValue::One => vec![value],
+        EnvValue::Paths => env::split_paths(&value)
+            .map(|path| path.display().to_string())
+            .collect(),
+        EnvValue::List => value.split(',').map(|v| v.trim().to_owned()).collect(),
+        EnvValue::Lines => value
+            .split(['\n', ';'])
+            .map(|v| v.trim().to_owned())
+            .collect(),
+    };
+
+    values
+        .into_iter()
+        .filter(|value| !value.is_empty())
+        .flat_map(|value| {
+            if flag.starts_with("--") {
+                vec![format!("{flag}={value}")]
+            } else {
+                vec![flag.to_owned(), value]
+            }
+        })
+        .collect()
+}
+
+/// Merges options from `CARGO_WATCH_*` environment variables under the
+/// command-line arguments.
+///
+/// Like config, they're inserted before the user's own arguments and parsed
+/// again. The returned arguments then stand in for the command-line, so the
+/// environment also takes precedence over config.
+pub fn apply_env(
+    cli_args: &[String],
+    matches: ArgMatches<'static>,
+    origins: &mut Origins,
+) -> (Vec<String>, ArgMatches<'static>) {
+    for (id, ..) in ENV_OPTIONS {
+        if matches.occurrences_of(id) > 0 {
+            origins.add(id, "command line");
+        }
+    }
+
+    let mut extra = Vec::new();
+    for (id, name, kind) in ENV_OPTIONS {
+        let var = format!("{ENV_PREFIX}{name}");
+        let args = env_args(&var, &env_flag(name), *kind);
+        if args.is_empty() || (!ACCUMULATE.contains(id) && is_set_on_cli(&matches, id)) {
+            continue;
+        }
+
+        origins.add(id, var);
+        extra.extend(args);
+    }
+
+    if extra.is_empty() {
+        return (cli_args.to_vec(), matches);
+    }
+
+    let mut merged = Vec::with_capacity(cli_args.len() + extra.len());
+    merged.extend(cli_args.iter().take(1).cloned());
+    merged.extend(extra);
+    merged.extend(cli_args.iter().skip(1).cloned());
+    let matches = args::parse(&merged);
+    (merged, matches)
+}
+
+/// Merges project config under the command-line arguments.
+///
+/// Config is turned back into arguments and inserted before the user's own,
+/// then everything is parsed again, so that the rest of cargo-watch only has to
+/// deal with `ArgMatches`.
+pub fn apply(
+    cli_args: &[String],
+    matches: ArgMatches<'static>,
+    origins: &mut Origins,
+) -> ArgMatches<'static> {
+    let layers: Vec<Layer> = load(matches.value_of("profile"))
+        .into_iter()
+        .map(|(origin, config)| Layer {
+            origin,
+            args: config.to_args(),
+        })
+        .collect();
+
+    if layers.is_empty() {
+        debug!("No config found");
+        return matches;
+    }
+
+    let mut taken = HashSet::new();
+    let mut extra: Vec<Vec<String>> = Vec::new();
+    for layer in &layers {
+        debug!("Config from {}: {:?}", layer.origin, layer.args);
+        let mut from_layer = Vec::new();
+        for (id, argv) in &layer.args {
+            if ACCUMULATE.contains(id) {
+                from_layer.extend(argv.iter().cloned());
+            } else if !is_set_on_cli(&matches, id) && !taken.contains(id) {
+                extra.push(argv.clone());
+            } else {
+                continue;
+            }
+            origins.add(id, layer.origin.clone());
+        }
+
+        // higher-priority layers have already claimed their options, and
+        // those which go with them
+        for (id, _) in &layer.args {
+            taken.extend(group(id));
+        }
+
+        // accumulated values go lowest-priority first, so later ones win
+        extra.insert(0, from_layer);
+    }
+
+    let extra: Vec<String> = extra.into_iter().flatten().collect();
+    if extra.is_empty() {
+        return matches;
+    }
+
+    debug!("Arguments from config: {:?}", extra);
+    let mut merged = Vec::with_capacity(cli_args.len() + extra.len());
+    merged.extend(cli_args.iter().take(1).cloned());
+    merged.extend(extra);
+    merged.extend(cli_args.iter().skip(1).cloned());
+    args::parse(&merged)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(shell: &str, profiles: &[(&str, &str)]) -> WatchConfig {
+        WatchConfig {
+            shell: Some(OneOrMany::One(shell.into())),
+            profile: profiles
+                .iter()
+                .map(|(name, shell)| (name.to_string(), config(shell, &[])))
+                .collect(),
+            ..WatchConfig::default()
+        }
+    }
+
+    #[test]
+    fn profiles_come_before_all_base_tables() {
+        let mut found = Found::default();
+        found.push("crate".into(), config("crate", &[]), Some("server"));
+        found.push(
+            "workspace".into(),
+            config("workspace", &[("server", "server"), ("tests", "tests")]),
+            Some("server"),
+        );
+
+        let origins: Vec<String> = found
+            .finish(Some("server"))
+            .into_iter()
+            .map(|(origin, _)| origin)
+            .collect();
+        assert_eq!(
+            origins,
+            ["profile 'server' in workspace", "crate", "workspace"]
+        );
+    }
+}
@@ -0,0 +1,184 @@
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    fs,
+    hash::Hasher,
+    io,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    thread,
+    time::SystemTime,
+};
+
+use log::{debug, warn};
+use watchexec::config::Config;
+
+use crate::filter::Filter;
+
+/// Files bigger than this aren't hashed, and changes to them always count.
+const MAX_HASHED_LEN: u64 = 32 * 1024 * 1024;
+
+/// Hashes of the contents of the watched files, for `--skip-unchanged`, to
+/// tell apart changes from files being written with the same contents.
+///
+/// The watched files which aren't ignored are hashed in the background when
+/// watching starts, so that the first change to a file is told apart too.
+#[derive(Debug, Default)]
+pub struct Contents {
+    hashes: Arc<Mutex<HashMap<PathBuf, u64>>>,
+}
+
+impl Contents {
+    /// Hashes the files under the paths to watch in the background, leaving
+    /// out those which the watcher ignores.
+    pub fn scan(&self, args: &Config) {
+        let filter = match Filter::new(args) {
+            Ok(filter) => filter,
+            Err(err) => {
+                warn!("Not hashing the watched files: {}", err);
+                return;
+            }
+        };
+        let mut roots: Vec<PathBuf> = args
+            .paths
+            .iter()
+            .filter_map(|path| path.canonicalize().ok())
+            .collect();
+        // paths inside others are walked with them
+        roots.sort();
+        roots.dedup_by(|inner, outer| inner.starts_with(outer));
+
+        let contents = Self {
+            hashes: Arc::clone(&self.hashes),
+        };
+        thread::spawn(move || {
+            let count = contents.hash_all(&roots, filter);
+            debug!("Hashed {} watched files", count);
+        });
+    }
+
+    /// Hashes the files under the paths, and returns how many were hashed.
+    ///
+    /// Files which changed since this started are left to the watcher, as
+    /// what they were before that is gone.
+    fn hash_all(&self, roots: &[PathBuf], mut filter: Filter) -> usize {
+        let started = SystemTime::now();
+        let mut count = 0;
+        for root in roots {
+            filter.enter_root(root);
+            let mut dirs = vec![root.clone()];
+            while let Some(dir) = dirs.pop() {
+                if filter.skips_dir(&dir) {
+                    continue;
+                }
+
+                filter.enter_dir(&dir);
+                for entry in fs::read_dir(&dir).into_iter().flatten().flatten() {
+                    let path = entry.path();
+                    match entry.file_type() {
+                        Ok(kind) if kind.is_dir() => dirs.push(path),
+                        Ok(_) if !filter.is_excluded(&path) => {
+                            let unchanged = entry
+                                .metadata()
+                                .and_then(|metadata| metadata.modified())
+                                .is_ok_and(|modified| modified < started);
+                            if let (true, Ok(Some(hash))) = (unchanged, hash_file(&path)) {
+                                self.hashes.lock().unwrap().entry(path).or_insert(hash);
+                                count += 1;
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+
+        count
+    }
+
+    /// Hashes the file again, and returns whether its contents changed.
+    ///
+    /// Files not hashed before, removed files, directories, and files too big
+    /// to hash count as changed. Files which are gone and were never seen, like
+    /// the temporary files editors write and rename, don't.
+    pub fn changed(&self, path: &Path) -> bool {
+        let hash = hash_file(path);
+        let mut hashes = self.hashes.lock().unwrap();
+        match hash {
+            Ok(Some(hash)) => hashes.insert(path.to_path_buf(), hash) != Some(hash),
+            Ok(None) => true,
+            Err(_) => hashes.remove(path).is_some(),
+        }
+    }
+}
+
+/// The hash of the file's contents, or `None` if it isn't a file or is too
+/// big to hash.
+fn hash_file(path: &Path) -> io::Result<Option<u64>> {
+    let metadata = fs::metadata(path)?;
+    if !metadata.is_file() || metadata.len() > MAX_HASHED_LEN {
+        return Ok(None);
+    }
+
+    let mut hasher = DefaultHasher::new();
+    hasher.write(&fs::read(path)?);
+    Ok(Some(hasher.finish()))
+}
+
+#[cfg(test)]
+mod tests {
+    use watchexec::config::ConfigBuilder;
+
+    use super::*;
+
+    #[test]
+    fn tells_apart_changes() {
+        let dir = std::env::temp_dir().join(format!("cargo-watch-contents-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("lib.rs");
+        let contents = Contents::default();
+
+        fs::write(&file, "one").unwrap();
+        assert!(contents.changed(&file), "first seen");
+        fs::write(&file, "one").unwrap();
+        assert!(!contents.changed(&file), "same contents");
+        fs::write(&file, "two").unwrap();
+        assert!(contents.changed(&file), "new contents");
+
+        fs::remove_file(&file).unwrap();
+        assert!(contents.changed(&file), "removed");
+        assert!(!contents.changed(&dir.join("never.tmp")), "never seen");
+        assert!(contents.changed(&dir), "directory");
+    }
+
+    #[test]
+    fn hashes_watched_files_up_front() {
+        let dir = std::env::temp_dir().join(format!("cargo-watch-scan-{}", std::process::id()));
+        fs::remove_dir_all(&dir).ok();
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::create_dir_all(dir.join("generated")).unwrap();
+        let dir = dir.canonicalize().unwrap();
+        fs::write(dir.join(".ignore"), "generated/\n").unwrap();
+        fs::write(dir.join("src/lib.rs"), "lib").unwrap();
+        fs::write(dir.join("notes.log"), "notes").unwrap();
+        fs::write(dir.join("generated/out.rs"), "out").unwrap();
+
+        let args = ConfigBuilder::default()
+            .cmd(vec!["true".to_string()])
+            .paths(vec![dir.clone()])
+            .ignores(vec!["*.log".to_string()])
+            .build()
+            .unwrap();
+        let contents = Contents::default();
+        contents.hash_all(std::slice::from_ref(&dir), Filter::new(&args).unwrap());
+
+        fs::write(dir.join("src/lib.rs"), "lib").unwrap();
+        assert!(!contents.changed(&dir.join("src/lib.rs")), "hashed");
+        fs::write(dir.join("notes.log"), "notes").unwrap();
+        assert!(contents.changed(&dir.join("notes.log")), "ignored");
+        fs::write(dir.join("generated/out.rs"), "out").unwrap();
+        assert!(
+            contents.changed(&dir.join("generated/out.rs")),
+            "in .ignore"
+        );
+    }
+}
@@ -0,0 +1,168 @@
+use std::{
+    ffi::CString,
+    io::{self, BufRead, BufReader, Write},
+    os::unix::{
+        ffi::OsStrExt,
+        net::{UnixListener, UnixStream},
+    },
+    path::{Path, PathBuf},
+    sync::{Arc, OnceLock},
+    thread,
+};
+
+use cargo_metadata::MetadataCommand;
+use log::{debug, warn};
+use serde::Serialize;
+use serde_json::json;
+
+use crate::{diagnostics::Summary, watch::CwHandler};
+
+/// The file name of the socket, in the target directory.
+const SOCKET_NAME: &str = "cargo-watch.sock";
+
+/// The path of the socket being listened on, to remove it when exiting.
+static SOCKET: OnceLock<CString> = OnceLock::new();
+
+/// How the last run ended, as reported by `status`.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct LastRun {
+    run: u64,
+    exit_status: Option<i32>,
+    success: bool,
+    diagnostics: Option<Summary>,
+}
+
+/// Listens on a socket in the target directory for commands, one per line:
+///
+/// - `rerun` stops the commands if they're running and runs them again,
+/// - `pause` and `resume` stop and start acting on changes,
+/// - `status` reports whether the commands are running, whether watching is
+///   paused, and how the last run ended.
+///
+/// Each command is answered with a line of JSON, with `ok` set to whether it
+/// was understood. The socket is removed when the returned guard is dropped,
+/// or when quitting.
+pub fn listen(handler: Arc<CwHandler>) -> io::Result<Socket> {
+    let path = socket_path();
+    if path.exists() {
+        if UnixStream::connect(&path).is_ok() {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                format!("another cargo-watch is listening on {}", path.display()),
+            ));
+        }
+
+        debug!("Removing stale socket {:?}", path);
+        std::fs::remove_file(&path)?;
+    }
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    let listener = UnixListener::bind(&path)?;
+    if let Ok(path) = CString::new(path.as_os_str().as_bytes()) {
+        SOCKET.set(path).ok();
+    }
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let handler = Arc::clone(&handler);
+                    thread::spawn(move || {
+                        serve(&handler, stream).unwrap_or_else(|err| {
+                            debug!("Control connection failed: {}", err);
+                        });
+                    });
+                }
+                Err(err) => warn!("Failed to accept a control connection: {}", err),
+            }
+        }
+    });
+
+    Ok(Socket(path))
+}
+
+/// Removes the socket, if one was opened.
+///
+/// This is async-signal-safe, so it can be called when terminating.
+pub fn remove() {
+    if let Some(path) = SOCKET.get() {
+        // SAFETY: unlink is async-signal-safe and the path is a valid C string
+        unsafe {
+            libc::unlink(path.as_ptr());
+        }
+    }
+}
+
+/// Removes the socket when dropped.
+#[must_use]
+pub struct Socket(PathBuf);
+
+impl Socket {
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for Socket {
+    fn drop(&mut self) {
+        remove();
+    }
+}
+
+fn socket_path() -> PathBuf {
+    let target = MetadataCommand::new()
+        .no_deps()
+        .exec()
+        .map(|metadata| metadata.target_directory.into_std_path_buf())
+        .unwrap_or_else(|err| {
+            debug!("Failed to find the target directory: {}", err);
+            PathBuf::from("target")
+        });
+    target.join(SOCKET_NAME)
+}
+
+fn serve(handler: &CwHandler, stream: UnixStream) -> io::Result<()> {
+    let mut writer = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        let reply = match line.trim() {
+            "" => continue,
+            "rerun" => match handler.rerun() {
+                Ok(()) => json!({ "ok": true }),
+                Err(err) => json!({ "ok": false, "error": err.to_string() }),
+            },
+            "pause" => {
+                handler.set_paused(true);
+                json!({ "ok": true })
+            }
+            "resume" => {
+                handler.set_paused(false);
+                json!({ "ok": true })
+            }
+            "status" => {
+                let last_run = handler.last_run().map(|outcome| LastRun {
+                    run: outcome.run,
+                    exit_status: outcome.status.and_then(|status| status.code()),
+                    success: outcome.status.is_some_and(|status| status.success()),
+                    diagnostics: outcome.diagnostics,
+                });
+                json!({
+                    "ok": true,
+                    "running": handler.is_running(),
+                    "paused": handler.is_paused(),
+                    "last-run": last_run,
+                })
+            }
+            other => json!({ "ok": false, "error": format!("unknown command '{other}'") }),
+        };
+
+        serde_json::to_writer(&mut writer, &reply)?;
+        writeln!(writer)?;
+    }
+
+    Ok(())
+}
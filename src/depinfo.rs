@@ -0,0 +1,264 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Component, Path, PathBuf},
+    time::SystemTime,
+};
+
+use cargo_metadata::Metadata;
+use log::debug;
+
+use crate::buildscript::BuildScripts;
+
+/// How deep under the target directory to look for dep-info files, enough
+/// for `<triple>/<profile>/build/<package>/`.
+const MAX_DEPTH: usize = 4;
+
+/// Directories under the target directory which have no dep-info files of
+/// interest, and can be big.
+const SKIP_DIRS: &[&str] = &["incremental", ".fingerprint", "doc", "tmp", "out"];
+
+/// The files which builds depend on, from the `.d` dep-info files cargo and
+/// rustc write under the target directory, for `--dep-info`.
+///
+/// These include every source file of each compiled target, and files read
+/// with `include_str!` and the like, wherever they are. Paths which build
+/// scripts declared with `rerun-if-changed` count too.
+#[derive(Clone, Debug)]
+pub struct DepInfo {
+    target_dir: PathBuf,
+    /// Relative paths in dep-info files are from here.
+    workspace_root: PathBuf,
+    /// The dependencies listed in each dep-info file, and when it was read.
+    read: HashMap<PathBuf, (SystemTime, Vec<PathBuf>)>,
+    files: HashSet<PathBuf>,
+    build_scripts: BuildScripts,
+    /// Files and directories from `rerun-if-changed`.
+    rerun_paths: Vec<PathBuf>,
+}
+
+impl DepInfo {
+    pub fn from_metadata(metadata: &Metadata) -> Self {
+        let mut dep_info = Self {
+            target_dir: metadata.target_directory.clone().into_std_path_buf(),
+            workspace_root: metadata.workspace_root.clone().into_std_path_buf(),
+            read: HashMap::new(),
+            files: HashSet::new(),
+            build_scripts: BuildScripts::from_metadata(metadata),
+            rerun_paths: Vec::new(),
+        };
+        dep_info.refresh();
+        dep_info
+    }
+
+    /// Reads the dep-info files written since the last time, and forgets
+    /// those which were removed.
+    pub fn refresh(&mut self) {
+        self.rerun_paths = self.build_scripts.declared().paths;
+
+        let mut found = Vec::new();
+        find_files(&self.target_dir, MAX_DEPTH, &mut found);
+
+        let mut changed = found.len() != self.read.len();
+        let mut read = HashMap::with_capacity(found.len());
+        for (file, modified) in found {
+            match self.read.remove(&file) {
+                Some((seen, deps)) if seen == modified => {
+                    read.insert(file, (seen, deps));
+                }
+                _ => {
+                    let deps = fs::read_to_string(&file)
+                        .map(|content| parse(&content, &self.workspace_root))
+                        .unwrap_or_default();
+                    read.insert(file, (modified, deps));
+                    changed = true;
+                }
+            }
+        }
+
+        self.read = read;
+        if changed {
+            self.files = self
+                .read
+                .values()
+                .flat_map(|(_, deps)| deps.iter().cloned())
+                .collect();
+            debug!(
+                "Builds depend on {} files, from {} dep-info files",
+                self.files.len(),
+                self.read.len()
+            );
+        }
+    }
+
+    /// Whether a change to the path could change a build.
+    ///
+    /// Before anything has been built, every path counts.
+    pub fn contains(&self, path: &Path) -> bool {
+        self.files.is_empty()
+            || self.files.contains(path)
+            || self.rerun_paths.iter().any(|rerun| path.starts_with(rerun))
+            || path
+                .file_name()
+                .is_some_and(|name| name == "Cargo.toml" || name == "Cargo.lock")
+    }
+}
+
+/// Finds `.d` files and when they were last written.
+fn find_files(dir: &Path, depth: usize, found: &mut Vec<(PathBuf, SystemTime)>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let file_type = match entry.file_type() {
+            Ok(file_type) => file_type,
+            Err(_) => continue,
+        };
+
+        if file_type.is_dir() {
+            let skip = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| SKIP_DIRS.contains(&name));
+            if depth > 0 && !skip {
+                find_files(&path, depth - 1, found);
+            }
+        } else if path.extension().is_some_and(|ext| ext == "d") {
+            if let Ok(modified) = entry.metadata().and_then(|m| m.modified()) {
+                found.push((path, modified));
+            }
+        }
+    }
+}
+
+/// The dependencies listed in a dep-info file, which is in Makefile syntax:
+/// `output: dependency dependency...`, with spaces in paths escaped.
+fn parse(content: &str, root: &Path) -> Vec<PathBuf> {
+    let mut deps = Vec::new();
+    for line in content.lines() {
+        if line.starts_with('#') {
+            continue;
+        }
+
+        // the output can be a Windows path, with its own colon
+        let rest = match line.find(": ") {
+            Some(colon) => &line[colon + 2..],
+            None => continue,
+        };
+
+        let mut dep = String::new();
+        let mut chars = rest.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => match chars.next() {
+                    Some(' ') => dep.push(' '),
+                    Some(other) => {
+                        dep.push('\\');
+                        dep.push(other);
+                    }
+                    None => {}
+                },
+                ' ' => {
+                    if !dep.is_empty() {
+                        deps.push(normalize(&root.join(&dep)));
+                        dep.clear();
+                    }
+                }
+                _ => dep.push(c),
+            }
+        }
+        if !dep.is_empty() {
+            deps.push(normalize(&root.join(&dep)));
+        }
+    }
+    deps
+}
+
+/// Removes `.` and `..` from a path without looking at the filesystem, as
+/// rustc lists files included from parent directories like `src/../data.txt`.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normal = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normal.pop();
+            }
+            other => normal.push(other),
+        }
+    }
+    normal
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paths(paths: &[&str]) -> Vec<PathBuf> {
+        paths.iter().map(PathBuf::from).collect()
+    }
+
+    #[test]
+    fn parses_dependencies() {
+        let content = "/ws/target/debug/foo: src/main.rs src/lib.rs\n\
+                       \n\
+                       src/main.rs:\n\
+                       src/lib.rs:\n";
+        assert_eq!(
+            parse(content, Path::new("/ws")),
+            paths(&["/ws/src/main.rs", "/ws/src/lib.rs"])
+        );
+    }
+
+    #[test]
+    fn parses_escaped_spaces() {
+        let content = "foo.d: src/with\\ space.rs src/plain.rs";
+        assert_eq!(
+            parse(content, Path::new("/ws")),
+            paths(&["/ws/src/with space.rs", "/ws/src/plain.rs"])
+        );
+    }
+
+    #[test]
+    fn parses_windows_outputs() {
+        let content = "C:\\ws\\target\\debug\\foo.d: src/main.rs";
+        assert_eq!(
+            parse(content, Path::new("/ws")),
+            paths(&["/ws/src/main.rs"])
+        );
+    }
+
+    #[test]
+    fn skips_comments() {
+        let content = "# env-dep:CARGO_PKG_NAME=foo\nfoo.d: src/main.rs";
+        assert_eq!(
+            parse(content, Path::new("/ws")),
+            paths(&["/ws/src/main.rs"])
+        );
+    }
+
+    #[test]
+    fn keeps_absolute_dependencies() {
+        let content = "foo.d: /elsewhere/data.txt";
+        assert_eq!(
+            parse(content, Path::new("/ws")),
+            paths(&["/elsewhere/data.txt"])
+        );
+    }
+
+    #[test]
+    fn normalises_parent_directories() {
+        let content = "foo.d: src/../data.txt src/./lib.rs ../shared/mod.rs";
+        assert_eq!(
+            parse(content, Path::new("/ws/foo")),
+            paths(&[
+                "/ws/foo/data.txt",
+                "/ws/foo/src/lib.rs",
+                "/ws/shared/mod.rs"
+            ])
+        );
+    }
+}
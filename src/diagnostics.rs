@@ -0,0 +1,352 @@
+use std::{
+    collections::HashSet,
+    env, fmt,
+    io::{self, BufReader, IsTerminal, Read, Write},
+    path::PathBuf,
+};
+
+use cargo_metadata::{diagnostic::DiagnosticLevel, Message, PackageId};
+use log::debug;
+use serde::Serialize;
+
+/// Given to build-like cargo commands so their diagnostics can be counted,
+/// when they're to be rendered in colour.
+const MESSAGE_FORMAT_ANSI: &str = "--message-format=json-diagnostic-rendered-ansi";
+
+/// The same, when they're to be rendered as plain text.
+const MESSAGE_FORMAT_PLAIN: &str = "--message-format=json";
+
+/// The message format to give a cargo command with these arguments, so the
+/// rendered diagnostics are printed as cargo would have: in colour if its
+/// `--color` or `CARGO_TERM_COLOR` say so, or by default if stderr is a
+/// terminal and `NO_COLOR` isn't set.
+pub fn message_format(args: &str) -> &'static str {
+    let term_color = env::var("CARGO_TERM_COLOR").ok();
+    let no_color = env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+    if wants_colour(
+        args,
+        term_color.as_deref(),
+        no_color,
+        io::stderr().is_terminal(),
+    ) {
+        MESSAGE_FORMAT_ANSI
+    } else {
+        MESSAGE_FORMAT_PLAIN
+    }
+}
+
+fn wants_colour(args: &str, term_color: Option<&str>, no_color: bool, terminal: bool) -> bool {
+    let mut words = args.split_whitespace();
+    let mut color = None;
+    while let Some(word) = words.next() {
+        if word == "--" {
+            break;
+        } else if word == "--color" {
+            color = words.next();
+        } else if let Some(value) = word.strip_prefix("--color=") {
+            color = Some(value);
+        }
+    }
+
+    match color.or(term_color) {
+        Some("always") => true,
+        Some("never") => false,
+        _ => terminal && !no_color,
+    }
+}
+
+/// Counts of compiler errors and warnings over a run.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct Summary {
+    pub errors: usize,
+    pub warnings: usize,
+    /// How many crates had errors or warnings.
+    pub crates: usize,
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.errors == 0 && self.warnings == 0 {
+            return write!(f, "no errors or warnings");
+        }
+
+        write!(
+            f,
+            "{}, {} in {}",
+            plural(self.errors, "error"),
+            plural(self.warnings, "warning"),
+            plural(self.crates, "crate")
+        )
+    }
+}
+
+fn plural(n: usize, word: &str) -> String {
+    if n == 1 {
+        format!("{n} {word}")
+    } else {
+        format!("{n} {word}s")
+    }
+}
+
+/// A binary or example built by a cargo command.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Executable {
+    pub package_id: PackageId,
+    /// The name of the target.
+    pub name: String,
+    pub example: bool,
+    pub path: PathBuf,
+}
+
+/// Collects diagnostics from the cargo commands of a run.
+#[derive(Debug, Default)]
+pub struct Collector {
+    errors: usize,
+    warnings: usize,
+    crates: HashSet<PackageId>,
+}
+
+impl Collector {
+    /// Reads cargo's JSON messages until the command closes its output, and
+    /// returns the binaries and examples it built.
+    ///
+    /// Rendered diagnostics go to stderr and anything that isn't a cargo
+    /// message, like test output, is passed through to stdout.
+    pub fn read(&mut self, output: impl Read) -> Vec<Executable> {
+        let mut executables = Vec::new();
+        let mut reader = BufReader::new(output);
+        for message in Message::parse_stream(&mut reader) {
+            match message {
+                Ok(Message::CompilerMessage(msg)) => {
+                    let diagnostic = &msg.message;
+                    if let Some(rendered) = &diagnostic.rendered {
+                        eprint!("{rendered}");
+                    }
+
+                    // rustc's own tallies are diagnostics too
+                    if diagnostic.spans.is_empty() && is_tally(&diagnostic.message) {
+                        continue;
+                    }
+
+                    match diagnostic.level {
+                        DiagnosticLevel::Error | DiagnosticLevel::Ice => self.errors += 1,
+                        DiagnosticLevel::Warning => self.warnings += 1,
+                        _ => continue,
+                    }
+                    self.crates.insert(msg.package_id);
+                }
+                Ok(Message::CompilerArtifact(artifact)) => {
+                    let target = artifact.target;
+                    let bin = target.kind.iter().any(|kind| kind == "bin");
+                    let example = target.kind.iter().any(|kind| kind == "example");
+                    if let (true, Some(path)) = (bin || example, artifact.executable) {
+                        executables.push(Executable {
+                            package_id: artifact.package_id,
+                            name: target.name,
+                            example,
+                            path: path.into_std_path_buf(),
+                        });
+                    }
+                }
+                Ok(Message::TextLine(line)) => println!("{line}"),
+                Ok(_) => {}
+                Err(err) => {
+                    // not UTF-8, so not from cargo: stop parsing and pass it all on
+                    debug!("Failed to read cargo messages: {}", err);
+                    io::copy(&mut reader, &mut io::stdout()).ok();
+                    break;
+                }
+            }
+        }
+
+        io::stdout().flush().ok();
+        executables
+    }
+
+    pub fn summary(&self) -> Summary {
+        Summary {
+            errors: self.errors,
+            warnings: self.warnings,
+            crates: self.crates.len(),
+        }
+    }
+}
+
+/// Whether this is a message like "aborting due to 2 previous errors" or
+/// "3 warnings emitted", rather than a diagnostic of its own.
+fn is_tally(message: &str) -> bool {
+    message.starts_with("aborting due to")
+        || message.ends_with("warning emitted")
+        || message.ends_with("warnings emitted")
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn target(kind: &str) -> serde_json::Value {
+        json!({ "name": "a", "kind": [kind], "src_path": "/a/src/main.rs" })
+    }
+
+    fn message(package: &str, level: &str, text: &str, spans: bool) -> String {
+        let spans = if spans {
+            json!([{
+                "file_name": "src/main.rs",
+                "byte_start": 0,
+                "byte_end": 1,
+                "line_start": 1,
+                "line_end": 1,
+                "column_start": 1,
+                "column_end": 2,
+                "is_primary": true,
+                "text": [],
+                "label": null,
+                "suggested_replacement": null,
+                "suggestion_applicability": null,
+                "expansion": null,
+            }])
+        } else {
+            json!([])
+        };
+
+        json!({
+            "reason": "compiler-message",
+            "package_id": package,
+            "target": target("bin"),
+            "message": {
+                "message": text,
+                "code": null,
+                "level": level,
+                "spans": spans,
+                "children": [],
+                "rendered": format!("{level}: {text}\n"),
+            },
+        })
+        .to_string()
+    }
+
+    fn artifact(kind: &str, executable: Option<&str>) -> String {
+        json!({
+            "reason": "compiler-artifact",
+            "package_id": "a 0.1.0 (path+file:///a)",
+            "target": target(kind),
+            "profile": {
+                "opt_level": "0",
+                "debuginfo": 2,
+                "debug_assertions": true,
+                "overflow_checks": true,
+                "test": false,
+            },
+            "features": [],
+            "filenames": [],
+            "executable": executable,
+            "fresh": false,
+        })
+        .to_string()
+    }
+
+    #[test]
+    fn counts_diagnostics() {
+        let output = [
+            message(
+                "a 0.1.0 (path+file:///a)",
+                "warning",
+                "unused variable",
+                true,
+            ),
+            message(
+                "a 0.1.0 (path+file:///a)",
+                "warning",
+                "1 warning emitted",
+                false,
+            ),
+            message(
+                "b 0.1.0 (path+file:///b)",
+                "error",
+                "mismatched types",
+                true,
+            ),
+            message("b 0.1.0 (path+file:///b)", "warning", "dead code", true),
+            message(
+                "b 0.1.0 (path+file:///b)",
+                "error",
+                "aborting due to 1 previous error",
+                false,
+            ),
+            String::from("running 0 tests"),
+        ]
+        .join("\n");
+
+        let mut collector = Collector::default();
+        collector.read(output.as_bytes());
+        assert_eq!(
+            collector.summary(),
+            Summary {
+                errors: 1,
+                warnings: 2,
+                crates: 2,
+            }
+        );
+    }
+
+    #[test]
+    fn finds_built_executables() {
+        let output = [
+            artifact("lib", None),
+            artifact("bin", Some("/a/target/debug/a")),
+            artifact("test", Some("/a/target/debug/deps/a-1234")),
+        ]
+        .join("\n");
+
+        let built = Collector::default().read(output.as_bytes());
+        assert_eq!(
+            built,
+            [Executable {
+                package_id: PackageId {
+                    repr: "a 0.1.0 (path+file:///a)".into()
+                },
+                name: "a".into(),
+                example: false,
+                path: "/a/target/debug/a".into(),
+            }]
+        );
+    }
+
+    #[test]
+    fn recognises_tallies() {
+        assert!(is_tally("aborting due to 2 previous errors"));
+        assert!(is_tally("1 warning emitted"));
+        assert!(is_tally("3 warnings emitted"));
+        assert!(!is_tally("unused variable: `emitted`"));
+    }
+
+    #[test]
+    fn summarises() {
+        assert_eq!(Summary::default().to_string(), "no errors or warnings");
+        let summary = Summary {
+            errors: 1,
+            warnings: 5,
+            crates: 3,
+        };
+        assert_eq!(summary.to_string(), "1 error, 5 warnings in 3 crates");
+    }
+
+    #[test]
+    fn colours_for_terminals() {
+        assert!(wants_colour("", None, false, true));
+        assert!(!wants_colour("", None, false, false));
+        assert!(!wants_colour("", None, true, true));
+    }
+
+    #[test]
+    fn colours_as_cargo_is_told() {
+        assert!(wants_colour("", Some("always"), true, false));
+        assert!(!wants_colour("", Some("never"), false, true));
+        assert!(wants_colour("", Some("auto"), false, true));
+        assert!(wants_colour(" --color=always", Some("never"), false, false));
+        assert!(!wants_colour(" --color never", None, false, true));
+        assert!(wants_colour(" -- --color never", None, false, true));
+    }
+}
@@ -0,0 +1,201 @@
+use std::{
+    ffi::OsString,
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+    thread,
+    time::{Duration, SystemTime},
+};
+
+use clap::{values_t, ArgMatches};
+use log::{error, info, warn};
+
+use crate::{pipeline::Step, watch::CwHandler};
+
+/// How often to check the `--env-file` files for changes.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A variable for the commands, maybe only for some of them.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Var {
+    /// The first word of the commands it's for, after `cargo` for cargo
+    /// commands, like `run` or `npm`.
+    scope: Option<String>,
+    name: String,
+    value: OsString,
+}
+
+/// Environment variables given to the commands, from `-B`, `-L`, `--env-file`
+/// and `-E`, later ones taking precedence.
+///
+/// They're added to each command as it's started, rather than set on
+/// cargo-watch itself, so they don't reach its own `cargo metadata` calls.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Environment {
+    vars: Vec<Var>,
+}
+
+impl Environment {
+    pub fn from_matches(matches: &ArgMatches) -> Result<Self, String> {
+        let mut env = Self::default();
+
+        if let Some(b) = matches.value_of("rust-backtrace") {
+            env.set("RUST_BACKTRACE", b);
+        }
+
+        if let Some(l) = matches.value_of("rust-log") {
+            env.set("RUST_LOG", l);
+        }
+
+        if matches.is_present("env-files") {
+            for file in values_t!(matches, "env-files", PathBuf).map_err(|e| e.message)? {
+                env.extend_from_file(&file)?;
+            }
+        }
+
+        if matches.is_present("env-vars") {
+            for pair in values_t!(matches, "env-vars", String).map_err(|e| e.message)? {
+                if let Some((name, value)) = pair.split_once('=') {
+                    match name.split_once(':') {
+                        Some((scope, name)) => env.vars.push(Var {
+                            scope: Some(scope.into()),
+                            name: name.into(),
+                            value: value.into(),
+                        }),
+                        None => env.set(name, value),
+                    }
+                } else {
+                    eprintln!("Malformed environment variable '{pair}', ignoring");
+                }
+            }
+        }
+
+        Ok(env)
+    }
+
+    /// Sets a variable for all commands.
+    pub fn set(&mut self, name: impl Into<String>, value: impl Into<OsString>) {
+        self.vars.push(Var {
+            scope: None,
+            name: name.into(),
+            value: value.into(),
+        });
+    }
+
+    /// Sets the variables from a `.env` file for all commands.
+    pub fn extend_from_file(&mut self, file: &Path) -> Result<(), String> {
+        let items = dotenvy::from_path_iter(file)
+            .map_err(|e| format!("Failed to read .env file {file:?}: {e}"))?;
+        for item in items {
+            let (name, value) =
+                item.map_err(|e| format!("Malformed pair in .env file {file:?}: {e}"))?;
+            self.set(name, value);
+        }
+
+        Ok(())
+    }
+
+    /// Names of the variables which are set differently in the other environment.
+    pub fn changed_names(&self, other: &Self) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .vars
+            .iter()
+            .filter(|var| !other.vars.contains(var))
+            .chain(other.vars.iter().filter(|var| !self.vars.contains(var)))
+            .map(|var| var.name.clone())
+            .collect();
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    /// The variables for a command, in order, so later ones win.
+    pub fn for_step(&self, step: &Step) -> Vec<(String, OsString)> {
+        let command = step.name.trim_start();
+        let first = command
+            .strip_prefix("cargo ")
+            .unwrap_or(command)
+            .split_whitespace()
+            .next();
+
+        self.vars
+            .iter()
+            .filter(|var| var.scope.is_none() || var.scope.as_deref() == first)
+            .map(|var| (var.name.clone(), var.value.clone()))
+            .collect()
+    }
+}
+
+/// Checks the `--env-file` files for changes in the background, and when they
+/// change, reads them again and reruns the commands with the new variables.
+///
+/// The files are polled, so they're followed even if they're ignored or
+/// outside the watched paths. If they can't be read, the error is reported and
+/// the previous variables are kept.
+pub fn watch_files(handler: Arc<CwHandler>, files: Vec<PathBuf>) {
+    thread::spawn(move || {
+        let mut seen = stamps(&files);
+        loop {
+            thread::sleep(POLL_INTERVAL);
+            let now = stamps(&files);
+            if now == seen {
+                continue;
+            }
+            seen = now;
+
+            match handler.reload_env() {
+                Ok(Some(_)) if handler.is_paused() => info!("Env files changed"),
+                Ok(Some(declared)) => {
+                    if declared.is_empty() {
+                        info!("Env files changed, rerunning");
+                    } else {
+                        info!(
+                            "Env files changed, rerunning as build scripts depend on {}",
+                            declared.join(", ")
+                        );
+                    }
+                    handler.rerun().unwrap_or_else(|err| {
+                        warn!("Failed to rerun: {}", err);
+                    });
+                }
+                Ok(None) => {}
+                Err(err) => error!(
+                    "Failed to reload the env files, keeping the previous values: {}",
+                    err
+                ),
+            }
+        }
+    });
+}
+
+/// When each file was last modified and how big it is, if it exists.
+fn stamps(files: &[PathBuf]) -> Vec<Option<(SystemTime, u64)>> {
+    files
+        .iter()
+        .map(|file| {
+            let metadata = fs::metadata(file).ok()?;
+            Some((metadata.modified().ok()?, metadata.len()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_changed_variables() {
+        let mut before = Environment::default();
+        before.set("PROTOC", "/usr/bin/protoc");
+        before.set("RUST_LOG", "debug");
+        before.set("GONE", "1");
+
+        let mut after = Environment::default();
+        after.set("PROTOC", "/opt/protoc");
+        after.set("RUST_LOG", "debug");
+        after.set("NEW", "1");
+
+        assert_eq!(before.changed_names(&after), vec!["GONE", "NEW", "PROTOC"]);
+        assert!(after.changed_names(&after.clone()).is_empty());
+    }
+}
@@ -0,0 +1,250 @@
+use std::{
+    fs::File,
+    io::{self, LineWriter, Write},
+    path::{Path, PathBuf},
+    process::ExitStatus,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use log::warn;
+use serde::Serialize;
+
+use crate::{
+    diagnostics::Summary,
+    pipeline::{describe, Outcome},
+};
+
+/// Something cargo-watch did, as reported to the user or to tools.
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub enum Event<'a> {
+    /// Paths changed, which will cause a run.
+    Changed { paths: Vec<&'a Path> },
+    /// A run is starting.
+    RunStarted { run: u64, commands: Vec<&'a str> },
+    /// A command of the run is starting. Steps are counted from 1.
+    #[serde(rename_all = "kebab-case")]
+    CommandStarted {
+        run: u64,
+        command: &'a str,
+        step: usize,
+        steps: usize,
+    },
+    /// A command of the run has finished.
+    #[serde(rename_all = "kebab-case")]
+    CommandFinished {
+        run: u64,
+        command: &'a str,
+        step: usize,
+        steps: usize,
+        /// `None` if the command failed to start or exited without a code.
+        exit_status: Option<i32>,
+        success: bool,
+        #[serde(serialize_with = "as_secs")]
+        duration: Duration,
+        #[serde(skip)]
+        status: Option<ExitStatus>,
+    },
+    /// A program built in place of `cargo run` has been started, replacing
+    /// the one started before, if any.
+    ProgramStarted {
+        run: u64,
+        command: &'a str,
+        program: &'a Path,
+    },
+    /// Changes are being ignored until watching is resumed.
+    Paused,
+    /// Changes are acted on again.
+    Resumed,
+    /// The run has finished, either after all commands or at the first failure.
+    #[serde(rename_all = "kebab-case")]
+    RunFinished {
+        run: u64,
+        /// `None` if a command failed to start or exited without a code.
+        exit_status: Option<i32>,
+        success: bool,
+        failed_command: Option<&'a str>,
+        /// Compiler errors and warnings, if any cargo command was run.
+        diagnostics: Option<&'a Summary>,
+        #[serde(serialize_with = "as_secs")]
+        duration: Duration,
+        #[serde(skip)]
+        status: Option<ExitStatus>,
+    },
+}
+
+fn as_secs<S: serde::Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(duration.as_secs_f64())
+}
+
+/// Callbacks for tools embedding cargo-watch, to follow what it does.
+///
+/// They're called from whichever thread is doing the work, so shouldn't block
+/// for long. All methods do nothing by default.
+pub trait RunObserver: Send + Sync {
+    /// Paths changed, which will cause a run.
+    fn on_change(&self, _paths: &[&Path]) {}
+
+    /// A run is starting, with these commands.
+    fn on_run_start(&self, _run: u64, _commands: &[&str]) {}
+
+    /// A command of the run is starting.
+    fn on_command_start(&self, _run: u64, _command: &str) {}
+
+    /// A command of the run has finished, or failed to start if there's no status.
+    fn on_command_finish(
+        &self,
+        _run: u64,
+        _command: &str,
+        _status: Option<ExitStatus>,
+        _duration: Duration,
+    ) {
+    }
+
+    /// The run has finished, either after all commands or at the first failure.
+    ///
+    /// Runs which are stopped to be restarted don't finish.
+    fn on_run_finish(&self, _outcome: &Outcome) {}
+}
+
+/// Writes events out in the selected format, and passes them on to observers.
+pub struct Reporter {
+    human: bool,
+    json: Option<Mutex<Box<dyn Write + Send>>>,
+    observers: Vec<Arc<dyn RunObserver>>,
+}
+
+impl Reporter {
+    /// Reports events as the usual `[Running ...]` lines, unless quiet.
+    pub fn human(quiet: bool) -> Self {
+        Self {
+            human: !quiet,
+            json: None,
+            observers: Vec::new(),
+        }
+    }
+
+    /// Reports events as newline-delimited JSON, to a file or to stdout.
+    ///
+    /// When writing to a file, the usual lines are still printed unless quiet.
+    pub fn json(quiet: bool, file: Option<PathBuf>) -> io::Result<Self> {
+        Ok(match file {
+            Some(path) => Self {
+                human: !quiet,
+                json: Some(Mutex::new(Box::new(LineWriter::new(File::create(path)?)))),
+                observers: Vec::new(),
+            },
+            None => Self {
+                human: false,
+                json: Some(Mutex::new(Box::new(io::stdout()))),
+                observers: Vec::new(),
+            },
+        })
+    }
+
+    /// Adds an observer, to be called after events are written out.
+    pub fn observe(&mut self, observer: Arc<dyn RunObserver>) {
+        self.observers.push(observer);
+    }
+
+    pub fn emit(&self, event: &Event) {
+        if let Some(json) = &self.json {
+            let mut out = json.lock().unwrap();
+            serde_json::to_writer(&mut *out, event)
+                .map_err(io::Error::from)
+                .and_then(|_| writeln!(out))
+                .and_then(|_| out.flush())
+                .unwrap_or_else(|err| warn!("Failed to write event: {}", err));
+        }
+
+        if self.human {
+            print_human(event);
+        }
+
+        for observer in &self.observers {
+            notify(observer.as_ref(), event);
+        }
+    }
+}
+
+fn notify(observer: &dyn RunObserver, event: &Event) {
+    match event {
+        Event::Changed { paths } => observer.on_change(paths),
+        Event::RunStarted { run, commands } => observer.on_run_start(*run, commands),
+        Event::CommandStarted { run, command, .. } => observer.on_command_start(*run, command),
+        Event::CommandFinished {
+            run,
+            command,
+            status,
+            duration,
+            ..
+        } => observer.on_command_finish(*run, command, *status, *duration),
+        Event::RunFinished {
+            run,
+            status,
+            diagnostics,
+            ..
+        } => observer.on_run_finish(&Outcome {
+            run: *run,
+            status: *status,
+            diagnostics: diagnostics.cloned(),
+        }),
+        Event::ProgramStarted { .. } | Event::Paused | Event::Resumed => {}
+    }
+}
+
+fn print_human(event: &Event) {
+    match event {
+        Event::Changed { .. } | Event::CommandStarted { .. } => {}
+        Event::RunStarted { commands, .. } => {
+            println!("[Running '{}']", commands.join(" && "));
+        }
+        Event::CommandFinished {
+            command,
+            steps,
+            duration,
+            status: Some(status),
+            ..
+        } => {
+            // the last line says it all when there's only one command
+            if *steps < 2 {
+                return;
+            }
+
+            if status.success() {
+                println!("[Finished '{}' in {:.2}s]", command, duration.as_secs_f64());
+            } else {
+                println!(
+                    "[Failed '{}' in {:.2}s. Exit status: {}]",
+                    command,
+                    duration.as_secs_f64(),
+                    describe(*status)
+                );
+            }
+        }
+        Event::CommandFinished { status: None, .. } => {}
+        Event::Paused => println!("[Paused]"),
+        Event::Resumed => println!("[Resumed]"),
+        Event::ProgramStarted { program, .. } => {
+            println!("[Started '{}']", program.display());
+        }
+        Event::RunFinished {
+            status,
+            diagnostics,
+            ..
+        } => {
+            let summary = diagnostics
+                .map(|summary| format!(". {summary}"))
+                .unwrap_or_default();
+            match status {
+                Some(status) => println!(
+                    "[Finished running. Exit status: {}{}]",
+                    describe(*status),
+                    summary
+                ),
+                None => println!("[Finished running. Failed to start{}]", summary),
+            }
+        }
+    }
+}
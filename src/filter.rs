@@ -0,0 +1,262 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use globset::{Glob, GlobBuilder, GlobSet, GlobSetBuilder};
+use watchexec::config::Config;
+
+/// Which paths the watcher reports changes to, worked out the way watchexec
+/// does, which doesn't expose its own: the ignores, then the filters, then
+/// `.ignore` files, then `.gitignore` files.
+///
+/// Ignore files are added as directories are walked, so that they apply to
+/// what's in them.
+#[derive(Debug)]
+pub struct Filter {
+    filters: GlobSet,
+    has_filters: bool,
+    ignores: GlobSet,
+    /// `.ignore` files, unless disabled.
+    dot_ignores: Option<Vec<IgnoreFile>>,
+    /// `.gitignore` files, unless disabled. They're only read in a git
+    /// repository, from its top level down.
+    gitignores: Option<Vec<IgnoreFile>>,
+    /// Whether the path being walked is in a git repository.
+    in_repo: bool,
+}
+
+impl Filter {
+    pub fn new(args: &Config) -> Result<Self, globset::Error> {
+        let mut filters = GlobSetBuilder::new();
+        for filter in &args.filters {
+            filters.add(Glob::new(filter)?);
+        }
+
+        let mut ignores = GlobSetBuilder::new();
+        for ignore in &args.ignores {
+            let mut pattern = PathBuf::from(ignore);
+            if pattern.is_relative() && !ignore.starts_with('*') {
+                pattern = Path::new("**").join(pattern);
+            }
+            if !ignore.ends_with('*') {
+                pattern = pattern.join("**");
+            }
+            ignores.add(Glob::new(&pattern.to_string_lossy())?);
+        }
+
+        Ok(Self {
+            filters: filters.build()?,
+            has_filters: !args.filters.is_empty(),
+            ignores: ignores.build()?,
+            dot_ignores: (!args.no_ignore).then(Vec::new),
+            gitignores: (!args.no_ignore && !args.no_vcs_ignore).then(Vec::new),
+            in_repo: false,
+        })
+    }
+
+    /// Reads the ignore files which apply to a watched path from the
+    /// directories above it.
+    pub fn enter_root(&mut self, root: &Path) {
+        if let Some(files) = &mut self.dot_ignores {
+            let mut above: Vec<&Path> = root.ancestors().skip(1).collect();
+            above.reverse();
+            for dir in above {
+                add_file(files, dir, ".ignore");
+            }
+        }
+
+        if let Some(files) = &mut self.gitignores {
+            let top = root.ancestors().find(|dir| dir.join(".git").is_dir());
+            self.in_repo = top.is_some();
+            if let Some(top) = top {
+                let mut above: Vec<&Path> = root
+                    .ancestors()
+                    .skip(1)
+                    .take_while(|dir| dir.starts_with(top))
+                    .collect();
+                above.reverse();
+                for dir in above {
+                    add_file(files, dir, ".gitignore");
+                }
+            }
+        }
+    }
+
+    /// Reads the ignore files in a directory, before what's in it is looked at.
+    pub fn enter_dir(&mut self, dir: &Path) {
+        if let Some(files) = &mut self.dot_ignores {
+            add_file(files, dir, ".ignore");
+        }
+
+        if let Some(files) = self.gitignores.as_mut().filter(|_| self.in_repo) {
+            add_file(files, dir, ".gitignore");
+        }
+    }
+
+    /// Whether changes to the path are left out.
+    pub fn is_excluded(&self, path: &Path) -> bool {
+        if self.ignores.is_match(path) {
+            return true;
+        }
+
+        if self.filters.is_match(path) {
+            return false;
+        }
+
+        if self
+            .dot_ignores
+            .as_deref()
+            .is_some_and(|files| ignored(files, path))
+            || self
+                .gitignores
+                .as_deref()
+                .is_some_and(|files| ignored(files, path))
+        {
+            return true;
+        }
+
+        self.has_filters
+    }
+
+    /// Whether nothing in the directory can be reported, so it needn't be
+    /// looked in. With filters, files in ignored directories can still be.
+    pub fn skips_dir(&self, dir: &Path) -> bool {
+        self.ignores.is_match(dir) || (!self.has_filters && self.is_excluded(dir))
+    }
+}
+
+/// The patterns in an ignore file, which apply to what's under its directory.
+#[derive(Debug)]
+struct IgnoreFile {
+    root: PathBuf,
+    set: GlobSet,
+    /// For each pattern, whether it starts with `!` to undo earlier ones.
+    whitelist: Vec<bool>,
+}
+
+impl IgnoreFile {
+    fn parse(root: &Path, contents: &str) -> Result<Self, globset::Error> {
+        let mut set = GlobSetBuilder::new();
+        let mut whitelist = Vec::new();
+        for line in contents.lines() {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (line, allow) = match line.strip_prefix('!') {
+                Some(line) => (line, true),
+                None => (line, false),
+            };
+            let (line, anchored) = match line.strip_prefix('/') {
+                Some(line) => (line, true),
+                None => (line, false),
+            };
+            let line = line.strip_suffix('/').unwrap_or(line);
+            let line = if line.starts_with("\\#") || line.starts_with("\\!") {
+                &line[1..]
+            } else {
+                line
+            };
+
+            let mut pattern = line.to_string();
+            if !anchored && !pattern.starts_with("**/") {
+                pattern = format!("**/{pattern}");
+            }
+            if !pattern.ends_with("/**") {
+                pattern.push_str("/**");
+            }
+
+            set.add(GlobBuilder::new(&pattern).literal_separator(true).build()?);
+            whitelist.push(allow);
+        }
+
+        Ok(Self {
+            root: root.to_path_buf(),
+            set: set.build()?,
+            whitelist,
+        })
+    }
+}
+
+/// Adds the ignore file in the directory, if there's one which can be read.
+fn add_file(files: &mut Vec<IgnoreFile>, dir: &Path, name: &str) {
+    if files.iter().any(|file| file.root == dir) {
+        return;
+    }
+
+    if let Ok(contents) = fs::read_to_string(dir.join(name)) {
+        if let Ok(file) = IgnoreFile::parse(dir, &contents) {
+            files.push(file);
+        }
+    }
+}
+
+/// Whether the last of the files which apply to the path, the deepest, that
+/// has a matching pattern, ignores it.
+fn ignored(files: &[IgnoreFile], path: &Path) -> bool {
+    let mut applicable: Vec<&IgnoreFile> = files
+        .iter()
+        .filter(|file| path.starts_with(&file.root))
+        .collect();
+    applicable.sort_by_key(|file| file.root.as_os_str().len());
+
+    let mut ignored = false;
+    for file in applicable {
+        let relative = path.strip_prefix(&file.root).unwrap_or(path);
+        if let Some(&last) = file.set.matches(relative).last() {
+            ignored = !file.whitelist[last];
+        }
+    }
+    ignored
+}
+
+#[cfg(test)]
+mod tests {
+    use watchexec::config::ConfigBuilder;
+
+    use super::*;
+
+    fn filter(ignores: &[&str], filters: &[&str]) -> Filter {
+        let args = ConfigBuilder::default()
+            .cmd(vec!["true".to_string()])
+            .paths(vec![PathBuf::from(".")])
+            .ignores(ignores.iter().map(|s| s.to_string()).collect::<Vec<_>>())
+            .filters(filters.iter().map(|s| s.to_string()).collect::<Vec<_>>())
+            .build()
+            .unwrap();
+        Filter::new(&args).unwrap()
+    }
+
+    #[test]
+    fn applies_ignores_then_filters() {
+        let filter = filter(&["*/target/**", "*.log"], &["*.rs", "*.log"]);
+        assert!(filter.is_excluded(Path::new("/p/target/debug/build.rs")));
+        assert!(filter.is_excluded(Path::new("/p/notes.log")));
+        assert!(!filter.is_excluded(Path::new("/p/src/lib.rs")));
+        assert!(filter.is_excluded(Path::new("/p/README.md")));
+
+        assert!(filter.skips_dir(Path::new("/p/target")));
+        assert!(!filter.skips_dir(Path::new("/p/src")));
+    }
+
+    #[test]
+    fn reads_ignore_files() {
+        let file = IgnoreFile::parse(
+            Path::new("/p"),
+            "# comment\n/out\ngenerated/\n*.snap\n!keep.snap\n\\#hash\n",
+        )
+        .unwrap();
+        let files = [file];
+        assert!(ignored(&files, Path::new("/p/out/a.rs")));
+        assert!(!ignored(&files, Path::new("/p/src/out/a.rs")), "anchored");
+        assert!(ignored(&files, Path::new("/p/src/generated/a.rs")));
+        assert!(ignored(&files, Path::new("/p/src/a.snap")));
+        assert!(
+            !ignored(&files, Path::new("/p/src/keep.snap")),
+            "whitelisted"
+        );
+        assert!(ignored(&files, Path::new("/p/#hash")));
+        assert!(!ignored(&files, Path::new("/q/a.snap")), "elsewhere");
+    }
+}
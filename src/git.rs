@@ -0,0 +1,164 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, SystemTime},
+};
+
+/// How long after git last touched its lock file or rebase state the
+/// operation is taken to be over: git may have died and left the lock file
+/// behind, or stopped a rebase for conflicts to be resolved.
+const STALE_AFTER: Duration = Duration::from_secs(5);
+
+/// The git repository the project is in, watched for operations which rewrite
+/// many files at once, like checkouts, rebases and stashes.
+///
+/// The `.git` directory is ignored, so this looks at it directly.
+#[derive(Debug)]
+pub struct Repo {
+    /// The git directory, which for a worktree is under the main repository's.
+    dir: PathBuf,
+    /// What `HEAD` was the last time it was looked at.
+    head: Mutex<Option<Vec<u8>>>,
+}
+
+impl Repo {
+    /// Finds the repository containing the current directory, if any.
+    pub fn find() -> Option<Self> {
+        let cwd = env::current_dir().ok()?;
+        let dir = cwd.ancestors().find_map(|dir| git_dir(&dir.join(".git")))?;
+        Some(Self::open(dir))
+    }
+
+    fn open(dir: PathBuf) -> Self {
+        let head = fs::read(dir.join("HEAD")).ok();
+        Self {
+            dir,
+            head: Mutex::new(head),
+        }
+    }
+
+    /// The operation in progress, if any.
+    pub fn operation(&self) -> Option<&'static str> {
+        self.operation_at(SystemTime::now())
+    }
+
+    fn operation_at(&self, now: SystemTime) -> Option<&'static str> {
+        let active = |name: &str| {
+            fs::metadata(self.dir.join(name))
+                .and_then(|metadata| metadata.modified())
+                .is_ok_and(|modified| {
+                    now.duration_since(modified)
+                        .map_or(true, |age| age < STALE_AFTER)
+                })
+        };
+
+        if active("rebase-merge") || active("rebase-apply") {
+            Some("git rebase")
+        } else if active("index.lock") {
+            Some("git operation")
+        } else {
+            None
+        }
+    }
+
+    /// Whether `HEAD` changed since the last time this was called, as it does
+    /// when switching branches.
+    pub fn head_moved(&self) -> bool {
+        let head = fs::read(self.dir.join("HEAD")).ok();
+        let mut last = self.head.lock().unwrap();
+        if *last == head {
+            return false;
+        }
+
+        *last = head;
+        true
+    }
+}
+
+/// The git directory a `.git` entry stands for: the directory itself, or for
+/// worktrees and submodules, the one a `.git` file points to.
+fn git_dir(dot_git: &Path) -> Option<PathBuf> {
+    if dot_git.is_dir() {
+        return Some(dot_git.to_path_buf());
+    }
+
+    let content = fs::read_to_string(dot_git).ok()?;
+    let dir = content.trim().strip_prefix("gitdir:")?.trim();
+    Some(dot_git.parent()?.join(dir))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh git directory for a test, with `HEAD` on main.
+    fn fixture(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("cargo-watch-git-{}-{}", name, std::process::id()));
+        fs::remove_dir_all(&dir).ok();
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("HEAD"), "ref: refs/heads/main\n").unwrap();
+        dir
+    }
+
+    #[test]
+    fn finds_operations() {
+        let dir = fixture("operations");
+        let repo = Repo::open(dir.clone());
+        assert_eq!(repo.operation(), None);
+
+        fs::write(dir.join("index.lock"), "").unwrap();
+        assert_eq!(repo.operation(), Some("git operation"));
+
+        fs::create_dir(dir.join("rebase-merge")).unwrap();
+        assert_eq!(repo.operation(), Some("git rebase"));
+
+        fs::remove_dir(dir.join("rebase-merge")).unwrap();
+        fs::create_dir(dir.join("rebase-apply")).unwrap();
+        assert_eq!(repo.operation(), Some("git rebase"));
+    }
+
+    #[test]
+    fn ignores_stale_operations() {
+        let dir = fixture("stale");
+        let repo = Repo::open(dir.clone());
+        fs::write(dir.join("index.lock"), "").unwrap();
+        fs::create_dir(dir.join("rebase-merge")).unwrap();
+
+        let later = SystemTime::now() + STALE_AFTER + Duration::from_secs(1);
+        assert_eq!(repo.operation_at(later), None);
+    }
+
+    #[test]
+    fn notices_head_moving() {
+        let dir = fixture("head");
+        let repo = Repo::open(dir.clone());
+        assert!(!repo.head_moved());
+
+        fs::write(dir.join("HEAD"), "ref: refs/heads/other\n").unwrap();
+        assert!(repo.head_moved());
+        assert!(!repo.head_moved());
+
+        fs::write(
+            dir.join("HEAD"),
+            "0123456789abcdef0123456789abcdef01234567\n",
+        )
+        .unwrap();
+        assert!(repo.head_moved());
+    }
+
+    #[test]
+    fn follows_git_files() {
+        let dir = fixture("worktree");
+        let worktree = dir.join("worktree");
+        fs::create_dir_all(&worktree).unwrap();
+        fs::write(worktree.join(".git"), "gitdir: ../worktrees/wt\n").unwrap();
+
+        assert_eq!(git_dir(&dir), Some(dir.clone()));
+        assert_eq!(
+            git_dir(&worktree.join(".git")),
+            Some(worktree.join("../worktrees/wt"))
+        );
+        assert_eq!(git_dir(&dir.join("missing")), None);
+    }
+}
@@ -0,0 +1,146 @@
+use std::{
+    io::{self, IsTerminal, Read},
+    process,
+    sync::Arc,
+    thread,
+};
+
+use log::{debug, warn};
+
+use crate::watch::CwHandler;
+
+/// Whether keys can be read: stdin has to be a terminal.
+pub fn available() -> bool {
+    cfg!(unix) && io::stdin().is_terminal()
+}
+
+/// Reads keys in the background and acts on them:
+///
+/// - `r` stops the commands if they're running and runs them again,
+/// - `c` clears the screen,
+/// - `p` pauses watching, or resumes it if paused,
+/// - `k` kills the commands, including a program started with `--keep-running`,
+/// - `q` kills the commands and quits.
+///
+/// The terminal is switched out of line mode so keys don't need Enter. Its
+/// settings are restored when quitting, or when the returned guard is dropped.
+/// The commands get no standard input, so they don't compete for keys.
+pub fn listen(handler: Arc<CwHandler>) -> Option<Terminal> {
+    if let Err(err) = terminal::enter() {
+        warn!("Failed to set up the terminal, keys won't work: {}", err);
+        return None;
+    }
+    handler.keep_stdin();
+
+    thread::spawn(move || {
+        let mut stdin = io::stdin();
+        let mut key = [0; 1];
+        loop {
+            match stdin.read(&mut key) {
+                Ok(0) => break,
+                Ok(_) => {}
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => {
+                    debug!("Failed to read from the terminal: {}", err);
+                    break;
+                }
+            }
+
+            match key[0] {
+                b'r' => {
+                    handler.rerun().unwrap_or_else(|err| {
+                        warn!("Failed to rerun: {}", err);
+                    });
+                }
+                b'c' => handler.clear_screen(),
+                b'p' => handler.set_paused(!handler.is_paused()),
+                b'k' => {
+                    handler.kill().unwrap_or_else(|err| {
+                        warn!("Failed to kill the command: {}", err);
+                    });
+                }
+                b'q' => {
+                    handler.kill().ok();
+                    terminal::restore();
+                    #[cfg(unix)]
+                    crate::control::remove();
+                    process::exit(0);
+                }
+                _ => {}
+            }
+        }
+
+        terminal::restore();
+    });
+
+    Some(Terminal(()))
+}
+
+/// Restores the terminal's settings when dropped, however cargo-watch exits.
+#[must_use]
+pub struct Terminal(());
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        terminal::restore();
+    }
+}
+
+#[cfg(unix)]
+pub mod terminal {
+    use std::{io, mem::MaybeUninit, sync::OnceLock};
+
+    /// The terminal settings from before keys were enabled.
+    static ORIGINAL: OnceLock<libc::termios> = OnceLock::new();
+
+    /// Turns off line buffering and echo, leaving signals like Ctrl-C alone.
+    pub(super) fn enter() -> io::Result<()> {
+        let mut termios = MaybeUninit::uninit();
+        // SAFETY: tcgetattr fills in the termios if it succeeds
+        let original = unsafe {
+            if libc::tcgetattr(libc::STDIN_FILENO, termios.as_mut_ptr()) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            termios.assume_init()
+        };
+        ORIGINAL.set(original).ok();
+
+        let mut keys = original;
+        keys.c_lflag &= !(libc::ICANON | libc::ECHO);
+        keys.c_cc[libc::VMIN] = 1;
+        keys.c_cc[libc::VTIME] = 0;
+
+        // SAFETY: the termios is a valid copy of the current one
+        if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &keys) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(())
+    }
+
+    /// Puts back the original settings, if they were changed.
+    ///
+    /// This is async-signal-safe, so it can be called when terminating.
+    pub fn restore() {
+        if let Some(original) = ORIGINAL.get() {
+            // SAFETY: tcsetattr is async-signal-safe and the termios is valid
+            unsafe {
+                libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, original);
+            }
+        }
+    }
+}
+
+#[cfg(not(unix))]
+pub mod terminal {
+    use std::io;
+
+    pub(super) fn enter() -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "keys are only supported on Unix",
+        ))
+    }
+
+    pub fn restore() {}
+}
@@ -0,0 +1,47 @@
+//! Cargo Watch as a library, for tools which want its Cargo-aware defaults:
+//! watching the crate and its local dependencies, ignoring `target/` and VCS
+//! files, and running cargo commands with features and packages applied.
+//!
+//! ```no_run
+//! let handler = cargo_watch::Builder::new()
+//!     .cargo("check")
+//!     .cargo("test")
+//!     .ignore("*.snap.new")
+//!     .build()?;
+//! handler.watch()?;
+//! # Ok::<(), watchexec::error::Error>(())
+//! ```
+//!
+//! [`RunObserver`]s given to the builder are told about each run.
+
+mod args;
+mod builder;
+mod buildscript;
+#[doc(hidden)]
+pub mod cli;
+mod completions;
+mod config;
+mod contents;
+#[cfg(unix)]
+mod control;
+mod depinfo;
+mod diagnostics;
+mod environment;
+mod events;
+mod filter;
+mod git;
+mod keys;
+mod options;
+mod pipeline;
+mod process;
+mod root;
+mod routes;
+mod waker;
+mod watch;
+mod workspace;
+
+pub use builder::Builder;
+pub use diagnostics::Summary;
+pub use events::RunObserver;
+pub use pipeline::Outcome;
+pub use watch::CwHandler;
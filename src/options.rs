@@ -1,11 +1,12 @@
 use std::{
     collections::{HashMap, HashSet},
+    env,
     iter::FromIterator,
-    path::{PathBuf, MAIN_SEPARATOR},
+    path::{Path, PathBuf, MAIN_SEPARATOR},
     time::Duration,
 };
 
-use cargo_metadata::{MetadataCommand, Node, Package, PackageId};
+use cargo_metadata::{Metadata, MetadataCommand, Node, Package, PackageId};
 use clap::{value_t, values_t, ArgMatches, ErrorKind};
 use log::{debug, warn};
 use watchexec::{
@@ -14,12 +15,33 @@ use watchexec::{
     Shell,
 };
 
-pub fn set_commands(builder: &mut ConfigBuilder, matches: &ArgMatches) {
-    let mut commands: Vec<String> = Vec::new();
+use crate::{diagnostics, pipeline::Step, routes::Routes};
 
-    // --features are injected just after applicable cargo subcommands
-    // and before the remaining arguments
+pub fn set_commands(builder: &mut ConfigBuilder, matches: &ArgMatches) -> Result<(), String> {
+    let packages = Packages::from_matches(matches)?;
+    let routes = Routes::from_matches(matches)?;
+
+    let mut steps = get_steps(matches, &packages)?;
+    if steps.is_empty() {
+        steps = route_steps(matches, &packages, &routes.commands());
+    }
+
+    let commands: Vec<String> = steps.into_iter().map(|step| step.name).collect();
+    debug!("Commands: {:?}", commands);
+    builder.cmd(commands);
+    Ok(())
+}
+
+/// The commands to run, with the given package selection applied to cargo commands.
+///
+/// This is empty if only `--on` commands were given.
+pub fn get_steps(matches: &ArgMatches, packages: &Packages) -> Result<Vec<Step>, String> {
+    let mut commands: Vec<Step> = Vec::new();
+
+    // --features and package selection are injected just after applicable
+    // cargo subcommands and before the remaining arguments
     let features = value_t!(matches, "features", String).ok();
+    let keep_running = matches.is_present("keep-running");
 
     let subcommand_cargo = {
         let (name, args) = matches.subcommand();
@@ -27,13 +49,11 @@ pub fn set_commands(builder: &mut ConfigBuilder, matches: &ArgMatches) {
             None
         } else if let Some(args) = args {
             let mut cargo_cmd = vec![name.to_string()];
-            cargo_cmd.extend(values_t!(args, "args", String).unwrap_or_else(|e| {
-                if e.kind == ErrorKind::ArgumentNotFound {
-                    Vec::new()
-                } else {
-                    e.exit()
-                }
-            }));
+            match values_t!(args, "args", String) {
+                Ok(args) => cargo_cmd.extend(args),
+                Err(e) if e.kind == ErrorKind::ArgumentNotFound => {}
+                Err(e) => return Err(e.message),
+            }
             Some(cargo_cmd.join(" "))
         } else {
             // shouldn't happen per clap2, but just in case:
@@ -43,56 +63,28 @@ pub fn set_commands(builder: &mut ConfigBuilder, matches: &ArgMatches) {
 
     // Cargo commands are in front of the rest
     if matches.is_present("cmd:cargo") || subcommand_cargo.is_some() {
-        let normal_cargos = values_t!(matches, "cmd:cargo", String).unwrap_or_else(|e| {
-            if e.kind == ErrorKind::ArgumentNotFound {
-                Vec::new()
-            } else {
-                e.exit()
-            }
-        });
+        let normal_cargos = match values_t!(matches, "cmd:cargo", String) {
+            Ok(cargos) => cargos,
+            Err(e) if e.kind == ErrorKind::ArgumentNotFound => Vec::new(),
+            Err(e) => return Err(e.message),
+        };
         for cargo in normal_cargos
             .into_iter()
             .chain(subcommand_cargo.into_iter())
         {
-            let mut cmd: String = "cargo ".into();
-            let cargo = cargo.trim_start();
-            // features are supported for the following
-            // (b)uild, bench, doc, (r)un, test, install
-            if let Some(features) = features.as_ref() {
-                if cargo.starts_with('b')
-                    || cargo.starts_with("check")
-                    || cargo.starts_with("doc")
-                    || cargo.starts_with('r')
-                    || cargo.starts_with("test")
-                    || cargo.starts_with("install")
-                {
-                    // Split command into first word and the arguments
-                    let word_boundary = cargo
-                        .find(|c: char| c.is_whitespace())
-                        .unwrap_or(cargo.len());
-
-                    // Find returns the byte index, and split_at takes a byte offset.
-                    // This means the splitting is unicode-safe.
-                    let (subcommand, args) = cargo.split_at(word_boundary);
-                    cmd.push_str(subcommand);
-                    cmd.push_str(" --features ");
-                    cmd.push_str(features);
-                    cmd.push(' ');
-                    cmd.push_str(args)
-                } else {
-                    cmd.push_str(cargo);
-                }
-            } else {
-                cmd.push_str(cargo);
-            }
-            commands.push(cmd);
+            commands.push(cargo_command(
+                &cargo,
+                features.as_deref(),
+                packages,
+                keep_running,
+            ));
         }
     }
 
     // Shell commands go last
     if matches.is_present("cmd:shell") {
-        for shell in values_t!(matches, "cmd:shell", String).unwrap_or_else(|e| e.exit()) {
-            commands.push(shell);
+        for shell in values_t!(matches, "cmd:shell", String).map_err(|e| e.message)? {
+            commands.push(Step::new(shell));
         }
     }
 
@@ -102,38 +94,227 @@ pub fn set_commands(builder: &mut ConfigBuilder, matches: &ArgMatches) {
             .value_of("use-shell")
             .map_or(false, |shell| shell.eq_ignore_ascii_case("none"))
         {
-            commands = values_t!(matches, "cmd:trail", String).unwrap_or_else(|e| e.exit());
+            let args = values_t!(matches, "cmd:trail", String).map_err(|e| e.message)?;
+            commands = vec![Step {
+                name: args.join(" "),
+                cmd: args,
+                diagnostics: false,
+                serve: None,
+            }];
         } else {
-            commands = vec![values_t!(matches, "cmd:trail", String)
-                .unwrap_or_else(|e| e.exit())
-                .into_iter()
-                .map(|arg| shell_escape::escape(arg.into()))
-                .collect::<Vec<_>>()
-                .join(" ")];
+            commands = vec![Step::new(
+                values_t!(matches, "cmd:trail", String)
+                    .map_err(|e| e.message)?
+                    .into_iter()
+                    .map(|arg| shell_escape::escape(arg.into()))
+                    .collect::<Vec<_>>()
+                    .join(" "),
+            )];
         }
     }
 
     // Default to `cargo check`
-    if commands.is_empty() {
-        let mut cmd: String = "cargo check".into();
-        if let Some(features) = features.as_ref() {
+    if commands.is_empty() && !matches.is_present("on") {
+        commands.push(cargo_command(
+            "check",
+            features.as_deref(),
+            packages,
+            keep_running,
+        ));
+    }
+
+    Ok(commands)
+}
+
+/// Whether the commands might read from the terminal: shell commands, `--on`
+/// rules and `cargo run` can, while other cargo commands don't.
+pub fn may_read_stdin(matches: &ArgMatches) -> bool {
+    let is_run = |cargo: &str| matches!(cargo.split_whitespace().next(), Some("run" | "r"));
+
+    matches.is_present("cmd:shell")
+        || matches.is_present("cmd:trail")
+        || matches.is_present("on")
+        || matches
+            .values_of("cmd:cargo")
+            .into_iter()
+            .flatten()
+            .any(is_run)
+}
+
+/// The `--on` commands to run. Those starting with `cargo` are treated like
+/// `-x` commands, with features, package selection and diagnostics.
+pub fn route_steps(matches: &ArgMatches, packages: &Packages, commands: &[&str]) -> Vec<Step> {
+    let features = value_t!(matches, "features", String).ok();
+    let keep_running = matches.is_present("keep-running");
+
+    commands
+        .iter()
+        .map(|command| match command.strip_prefix("cargo ") {
+            Some(cargo) => cargo_command(cargo, features.as_deref(), packages, keep_running),
+            None => Step::new(command.to_string()),
+        })
+        .collect()
+}
+
+/// Cargo subcommands (and their aliases) which take `-p`.
+const PACKAGE_SUBCOMMANDS: &[&str] = &[
+    "bench", "b", "build", "check", "c", "clippy", "doc", "d", "fix", "run", "r", "rustc",
+    "rustdoc", "test", "t",
+];
+
+/// Cargo subcommands (and their aliases) which take `-p` only once.
+const SINGLE_PACKAGE_SUBCOMMANDS: &[&str] = &["run", "r", "rustc", "rustdoc"];
+
+/// Cargo subcommands (and their aliases) which take `--workspace`.
+const WORKSPACE_SUBCOMMANDS: &[&str] = &[
+    "bench", "b", "build", "check", "c", "clippy", "doc", "d", "fix", "test", "t",
+];
+
+/// Cargo subcommands (and their aliases) whose diagnostics are collected
+/// for the summary at the end of each run.
+///
+/// `test` and `bench` are left out, as their output would go through
+/// cargo-watch, and test harnesses would then not see a terminal.
+const DIAGNOSTIC_SUBCOMMANDS: &[&str] = &[
+    "build", "b", "check", "c", "clippy", "doc", "d", "fix", "rustc", "rustdoc",
+];
+
+/// Workspace packages selected with `-p` or `--all`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Packages {
+    Default,
+    All,
+    Some(Vec<String>),
+    /// The members affected by changes, with `--affected`. Unlike packages
+    /// selected with `-p`, these aren't passed to commands which only take one.
+    Affected(Vec<String>),
+}
+
+impl Packages {
+    pub fn from_matches(matches: &ArgMatches) -> Result<Self, String> {
+        Ok(if matches.is_present("packages:all") {
+            Self::All
+        } else if matches.is_present("packages:one") {
+            Self::Some(values_t!(matches, "packages:one", String).map_err(|e| e.message)?)
+        } else {
+            Self::Default
+        })
+    }
+}
+
+/// Builds a `cargo` command line, injecting features, package selection, and
+/// the diagnostics message format for the subcommands that support them.
+///
+/// With `keep_running`, `cargo run` is split into a build and a run of the
+/// program it builds.
+fn cargo_command(
+    cargo: &str,
+    features: Option<&str>,
+    packages: &Packages,
+    keep_running: bool,
+) -> Step {
+    let cargo = cargo.trim_start();
+
+    // Split command into first word and the arguments
+    let word_boundary = cargo
+        .find(|c: char| c.is_whitespace())
+        .unwrap_or(cargo.len());
+
+    // Find returns the byte index, and split_at takes a byte offset.
+    // This means the splitting is unicode-safe.
+    let (subcommand, args) = cargo.split_at(word_boundary);
+
+    let mut cmd: String = "cargo ".into();
+    cmd.push_str(subcommand);
+
+    // features are supported for the following
+    // (b)uild, bench, doc, (r)un, test, install
+    if let Some(features) = features {
+        if cargo.starts_with('b')
+            || cargo.starts_with("check")
+            || cargo.starts_with("doc")
+            || cargo.starts_with('r')
+            || cargo.starts_with("test")
+            || cargo.starts_with("install")
+        {
             cmd.push_str(" --features ");
             cmd.push_str(features);
         }
-        commands.push(cmd);
     }
 
-    debug!("Commands: {:?}", commands);
-    builder.cmd(commands);
+    match packages {
+        Packages::Default => {}
+        Packages::All => {
+            if WORKSPACE_SUBCOMMANDS.contains(&subcommand) {
+                cmd.push_str(" --workspace");
+            }
+        }
+        Packages::Some(specs) => {
+            if PACKAGE_SUBCOMMANDS.contains(&subcommand) {
+                for spec in specs {
+                    cmd.push_str(" -p ");
+                    cmd.push_str(spec);
+                }
+            }
+        }
+        Packages::Affected(names) => {
+            if PACKAGE_SUBCOMMANDS.contains(&subcommand)
+                && !SINGLE_PACKAGE_SUBCOMMANDS.contains(&subcommand)
+            {
+                for name in names {
+                    cmd.push_str(" -p ");
+                    cmd.push_str(name);
+                }
+            }
+        }
+    }
+
+    let mut name = cmd.clone();
+    name.push_str(args);
+
+    if keep_running && (subcommand == "run" || subcommand == "r") {
+        // everything after `--` is for the program
+        let (build_args, program_args) = match args.split_once(" -- ") {
+            Some((build_args, program_args)) => (build_args, program_args),
+            None => (args.strip_suffix(" --").unwrap_or(args), ""),
+        };
+
+        let injected = &cmd["cargo ".len() + subcommand.len()..];
+        return Step {
+            name,
+            cmd: vec![format!(
+                "cargo build{injected} {}{build_args}",
+                diagnostics::message_format(build_args)
+            )],
+            diagnostics: true,
+            serve: Some(program_args.to_string()),
+        };
+    }
+
+    // leave it alone if the user has chosen a format
+    let diagnostics =
+        DIAGNOSTIC_SUBCOMMANDS.contains(&subcommand) && !args.contains("--message-format");
+    if diagnostics {
+        cmd.push(' ');
+        cmd.push_str(diagnostics::message_format(args));
+    }
+
+    cmd.push_str(args);
+    Step {
+        name,
+        cmd: vec![cmd],
+        diagnostics,
+        serve: None,
+    }
 }
 
-pub fn set_ignores(builder: &mut ConfigBuilder, matches: &ArgMatches) {
+pub fn set_ignores(builder: &mut ConfigBuilder, matches: &ArgMatches) -> Result<(), String> {
     if matches.is_present("ignore-nothing") {
         debug!("Ignoring nothing");
 
         builder.no_vcs_ignore(true);
         builder.no_ignore(true);
-        return;
+        return Ok(());
     }
 
     let novcs = matches.is_present("no-vcs-ignores");
@@ -171,7 +352,7 @@ pub fn set_ignores(builder: &mut ConfigBuilder, matches: &ArgMatches) {
     debug!("Default ignores: {:?}", list);
 
     if matches.is_present("ignore") {
-        for ignore in values_t!(matches, "ignore", String).unwrap_or_else(|e| e.exit()) {
+        for ignore in values_t!(matches, "ignore", String).map_err(|e| e.message)? {
             #[cfg(windows)]
             let ignore = ignore.replace('/', &MAIN_SEPARATOR.to_string());
             list.push(ignore);
@@ -180,24 +361,84 @@ pub fn set_ignores(builder: &mut ConfigBuilder, matches: &ArgMatches) {
 
     debug!("All ignores: {:?}", list);
     builder.ignores(list);
+    Ok(())
 }
 
-pub fn set_debounce(builder: &mut ConfigBuilder, matches: &ArgMatches) {
+/// Only paths matching these patterns trigger runs, if any are given. Ignores
+/// still apply on top.
+pub fn set_filters(builder: &mut ConfigBuilder, matches: &ArgMatches) -> Result<(), String> {
+    let mut list = Vec::new();
+
+    if matches.is_present("filter") {
+        let root = env::current_dir()
+            .and_then(|dir| dir.canonicalize())
+            .unwrap_or_else(|_| ".".into());
+        for filter in values_t!(matches, "filter", String).map_err(|e| e.message)? {
+            #[cfg(windows)]
+            let filter = filter.replace('/', &MAIN_SEPARATOR.to_string());
+            list.push(anchor_filter(&root, filter));
+        }
+    }
+
+    if matches.is_present("exts") {
+        for exts in values_t!(matches, "exts", String).map_err(|e| e.message)? {
+            list.extend(
+                exts.split(',')
+                    .map(|ext| ext.trim().trim_start_matches('.'))
+                    .filter(|ext| !ext.is_empty())
+                    .map(|ext| format!("*.{ext}")),
+            );
+        }
+    }
+
+    if !list.is_empty() {
+        debug!("Filters: {:?}", list);
+    }
+    builder.filters(list);
+    Ok(())
+}
+
+/// Filters are matched against full paths, so patterns with a separator are
+/// taken from the crate root, and others match file names anywhere.
+fn anchor_filter(root: &Path, filter: String) -> String {
+    if filter.starts_with('*') || Path::new(&filter).is_absolute() {
+        filter
+    } else if filter.contains(MAIN_SEPARATOR) {
+        root.join(filter).to_string_lossy().into_owned()
+    } else {
+        format!("*{MAIN_SEPARATOR}{filter}")
+    }
+}
+
+pub fn set_debounce(builder: &mut ConfigBuilder, matches: &ArgMatches) -> Result<(), String> {
     if matches.is_present("delay") {
-        let debounce = value_t!(matches, "delay", f32).unwrap_or_else(|e| e.exit());
+        let debounce = value_t!(matches, "delay", f32).map_err(|_| {
+            format!(
+                "Invalid delay '{}', expected a number of seconds",
+                matches.value_of("delay").unwrap_or_default()
+            )
+        })?;
         debug!("File updates debounce: {} seconds", debounce);
 
         let d = Duration::from_millis((debounce * 1000.0) as u64);
         builder.poll_interval(d).debounce(d);
     }
+
+    Ok(())
 }
 
-fn find_local_deps() -> Result<Vec<PathBuf>, String> {
-    let metadata = MetadataCommand::new()
+/// The project's packages and how they depend on each other, which the paths
+/// to watch, `--affected` and `--dep-info` are all worked out from.
+pub fn load_metadata() -> Result<Metadata, String> {
+    MetadataCommand::new()
         .exec()
-        .map_err(|e| format!("Failed to execute `cargo metadata`: {}", e))?;
+        .map_err(|e| format!("Failed to execute `cargo metadata`: {}", e))
+}
 
-    let resolve = match metadata.resolve {
+/// Directories of the selected packages (or of the current one), and of all
+/// the local packages they depend on.
+pub fn find_local_deps(metadata: &Metadata, packages: &Packages) -> Result<Vec<PathBuf>, String> {
+    let resolve = match &metadata.resolve {
         None => return Ok(Vec::new()),
         Some(resolve) => resolve,
     };
@@ -209,9 +450,21 @@ fn find_local_deps() -> Result<Vec<PathBuf>, String> {
 
     let mut pkgids_seen = HashSet::new();
     let mut pkgids_to_check = Vec::new();
-    match resolve.root {
-        Some(root) => pkgids_to_check.push(root),
-        None => pkgids_to_check.extend_from_slice(&metadata.workspace_members),
+    match (packages, resolve.root.clone()) {
+        (Packages::Some(specs) | Packages::Affected(specs), _) => {
+            let members: Vec<&Package> = metadata
+                .workspace_members
+                .iter()
+                .filter_map(|id| id_to_package.get(id).copied())
+                .collect();
+            for spec in specs {
+                pkgids_to_check.push(find_member(&members, spec)?.id.clone());
+            }
+        }
+        (Packages::Default, Some(root)) => pkgids_to_check.push(root),
+        (Packages::Default | Packages::All, _) => {
+            pkgids_to_check.extend_from_slice(&metadata.workspace_members)
+        }
     };
 
     // The set of directories of all packages we are interested in.
@@ -250,39 +503,132 @@ fn find_local_deps() -> Result<Vec<PathBuf>, String> {
     Ok(local_deps.into_iter().collect::<Vec<PathBuf>>())
 }
 
-pub fn set_watches(builder: &mut ConfigBuilder, matches: &ArgMatches) {
-    let mut watches = Vec::new();
-    if matches.is_present("watch") {
-        for watch in values_t!(matches, "watch", String).unwrap_or_else(|e| e.exit()) {
-            watches.push(watch.into());
-        }
-    }
+/// The workspace member a `-p` spec stands for, by `name` or `name@version`.
+fn find_member<'p>(members: &[&'p Package], spec: &str) -> Result<&'p Package, String> {
+    let (name, version) = match spec.split_once('@') {
+        Some((name, version)) => (name, Some(version)),
+        None => (spec, None),
+    };
 
-    if watches.is_empty() {
-        if !matches.is_present("skip-local-deps") {
-            match find_local_deps() {
-                Ok(dirs) => {
-                    if dirs.is_empty() {
-                        debug!("Found no local deps");
-                    } else {
-                        watches = dirs;
-                    }
-                }
-                Err(err) => {
-                    // If this fails just fall back to watching the current directory.
-                    eprintln!("Finding local deps failed: {}", err);
-                }
+    members
+        .iter()
+        .copied()
+        .find(|pkg| pkg.name == name && version.map_or(true, |v| pkg.version.to_string() == v))
+        .ok_or_else(|| format!("package `{}` not found in workspace", spec))
+}
+
+/// Names to complete for an option, from the workspace's metadata: `packages`
+/// are the members, `features` those of the current package, and `bins`,
+/// `tests`, `examples` and `benches` the members' targets of that kind.
+pub fn completion_values(kind: &str) -> Result<Vec<String>, String> {
+    let metadata = MetadataCommand::new()
+        .no_deps()
+        .exec()
+        .map_err(|e| format!("Failed to execute `cargo metadata`: {}", e))?;
+
+    // Without dependencies, the packages are the workspace members.
+    let mut values: Vec<String> = match kind {
+        "packages" => metadata.packages.iter().map(|p| p.name.clone()).collect(),
+        "features" => {
+            let cwd = std::env::current_dir().map_err(|e| e.to_string())?;
+            let current = metadata
+                .packages
+                .iter()
+                .filter(|p| {
+                    p.manifest_path
+                        .parent()
+                        .is_some_and(|dir| cwd.starts_with(dir))
+                })
+                .max_by_key(|p| p.manifest_path.components().count());
+
+            // At the root of a virtual workspace, offer every member's features
+            match current {
+                Some(pkg) => pkg.features.keys().cloned().collect(),
+                None => metadata
+                    .packages
+                    .iter()
+                    .flat_map(|p| p.features.keys().cloned())
+                    .collect(),
             }
         }
+        "bins" => target_names(&metadata.packages, "bin"),
+        "tests" => target_names(&metadata.packages, "test"),
+        "examples" => target_names(&metadata.packages, "example"),
+        "benches" => target_names(&metadata.packages, "bench"),
+        other => return Err(format!("nothing to complete for `{}`", other)),
+    };
 
-        watches.push(".".into());
-    }
+    values.sort();
+    values.dedup();
+    Ok(values)
+}
+
+fn target_names(packages: &[Package], kind: &str) -> Vec<String> {
+    packages
+        .iter()
+        .flat_map(|p| &p.targets)
+        .filter(|t| t.kind.iter().any(|k| k == kind))
+        .map(|t| t.name.clone())
+        .collect()
+}
+
+/// Sets the paths given with `--watch`, or else the current directory, which
+/// the handler replaces with the local packages once it has found them.
+pub fn set_watches(builder: &mut ConfigBuilder, matches: &ArgMatches) -> Result<(), String> {
+    let watches = if matches.is_present("watch") {
+        values_t!(matches, "watch", String)
+            .map_err(|e| e.message)?
+            .into_iter()
+            .map(PathBuf::from)
+            .collect()
+    } else {
+        vec![".".into()]
+    };
 
     debug!("Watches: {:?}", watches);
     builder.paths(watches);
+    Ok(())
+}
+
+/// Without `--watch`, the paths to watch: the local packages, and the paths
+/// their build scripts declared which are outside of those.
+pub fn get_watches(
+    matches: &ArgMatches,
+    metadata: &Metadata,
+    declared: &[PathBuf],
+) -> Result<Vec<PathBuf>, String> {
+    let mut watches = Vec::new();
+    let packages = Packages::from_matches(matches)?;
+    if !matches.is_present("skip-local-deps") {
+        watches = find_local_deps(metadata, &packages)?;
+        if watches.is_empty() {
+            debug!("Found no local deps");
+        }
+    }
+
+    // When specific packages are selected, don't widen the watch to the
+    // whole project unless nothing else was found.
+    if watches.is_empty() || !matches!(packages, Packages::Some(_)) {
+        watches.push(".".into());
+    }
+
+    // Build scripts may depend on files outside of the packages
+    let watched: Vec<PathBuf> = watches
+        .iter()
+        .filter_map(|watch| watch.canonicalize().ok())
+        .collect();
+    for path in declared {
+        if !watched.iter().any(|watch| path.starts_with(watch)) {
+            debug!("Watching {:?} for a build script", path);
+            watches.push(path.clone());
+        }
+    }
+
+    Ok(watches)
 }
 
-pub fn get_options(matches: &ArgMatches) -> Config {
+/// The watchexec configuration for the arguments, or why they don't make sense.
+pub fn get_options(matches: &ArgMatches) -> Result<Config, String> {
     let mut builder = ConfigBuilder::default();
     builder
         .poll(matches.is_present("poll"))
@@ -320,16 +666,17 @@ pub fn get_options(matches: &ArgMatches) -> Config {
         default_shell()
     });
 
-    set_ignores(&mut builder, matches);
-    set_debounce(&mut builder, matches);
-    set_watches(&mut builder, matches);
-    set_commands(&mut builder, matches);
+    set_ignores(&mut builder, matches)?;
+    set_filters(&mut builder, matches)?;
+    set_debounce(&mut builder, matches)?;
+    set_watches(&mut builder, matches)?;
+    set_commands(&mut builder, matches)?;
 
     let mut args = builder.build().unwrap();
     args.once = matches.is_present("once");
 
     debug!("Watchexec arguments: {:?}", args);
-    args
+    Ok(args)
 }
 
 // until next breaking
@@ -353,3 +700,152 @@ fn cmd_shell(_: String) -> Shell {
 fn cmd_shell(s: String) -> Shell {
     Shell::Unix(s)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn package(name: &str, version: &str) -> Package {
+        serde_json::from_value(serde_json::json!({
+            "name": name,
+            "version": version,
+            "id": format!("{name} {version} (path+file:///ws/{name})"),
+            "dependencies": [],
+            "targets": [],
+            "features": {},
+            "manifest_path": format!("/ws/{name}/Cargo.toml"),
+        }))
+        .unwrap()
+    }
+
+    fn some(specs: &[&str]) -> Packages {
+        Packages::Some(specs.iter().map(|spec| spec.to_string()).collect())
+    }
+
+    #[test]
+    fn injects_packages() {
+        let step = cargo_command("test --lib", None, &some(&["a", "b@0.1.0"]), false);
+        assert_eq!(step.name, "cargo test -p a -p b@0.1.0 --lib");
+    }
+
+    #[test]
+    fn injects_workspace() {
+        let step = cargo_command("clippy", None, &Packages::All, false);
+        assert_eq!(step.name, "cargo clippy --workspace");
+    }
+
+    #[test]
+    fn leaves_other_subcommands_alone() {
+        let step = cargo_command("fmt --check", None, &some(&["a"]), false);
+        assert_eq!(step.name, "cargo fmt --check");
+        assert_eq!(step.cmd, ["cargo fmt --check"]);
+
+        let step = cargo_command("run", None, &Packages::All, false);
+        assert_eq!(step.name, "cargo run");
+    }
+
+    #[test]
+    fn injects_affected_packages() {
+        let affected = Packages::Affected(vec!["a".into(), "b".into()]);
+        let step = cargo_command("check", None, &affected, false);
+        assert_eq!(step.name, "cargo check -p a -p b");
+    }
+
+    #[test]
+    fn leaves_single_package_subcommands_alone_when_affected() {
+        let affected = Packages::Affected(vec!["a".into(), "b".into()]);
+        for command in [
+            "run --bin server",
+            "r",
+            "rustc -- -Zunpretty=expanded",
+            "rustdoc",
+        ] {
+            let step = cargo_command(command, None, &affected, false);
+            assert_eq!(step.name, format!("cargo {command}"));
+        }
+
+        // but a package chosen with -p is passed on
+        let step = cargo_command("run", None, &some(&["a"]), false);
+        assert_eq!(step.name, "cargo run -p a");
+    }
+
+    #[test]
+    fn splits_run_into_build_and_program() {
+        let step = cargo_command("run --bin server", None, &Packages::Default, true);
+        assert_eq!(step.name, "cargo run --bin server");
+        assert_eq!(
+            step.cmd,
+            [format!(
+                "cargo build {} --bin server",
+                diagnostics::message_format(" --bin server")
+            )]
+        );
+        assert!(step.diagnostics);
+        assert_eq!(step.serve.as_deref(), Some(""));
+    }
+
+    #[test]
+    fn passes_arguments_after_dashes_to_the_program() {
+        let step = cargo_command("r -p a -- --port 80 -- x", None, &Packages::Default, true);
+        assert!(step.cmd[0].starts_with("cargo build --message-format="));
+        assert!(step.cmd[0].ends_with(" -p a"));
+        assert_eq!(step.serve.as_deref(), Some("--port 80 -- x"));
+
+        let step = cargo_command("run --release --", None, &some(&["a"]), true);
+        assert!(step.cmd[0].starts_with("cargo build -p a --message-format="));
+        assert!(step.cmd[0].ends_with(" --release"));
+        assert_eq!(step.serve.as_deref(), Some(""));
+    }
+
+    #[test]
+    fn leaves_run_alone_without_keep_running() {
+        let step = cargo_command("run -- --port 80", None, &Packages::Default, false);
+        assert_eq!(step.cmd, ["cargo run -- --port 80"]);
+        assert_eq!(step.serve, None);
+    }
+
+    #[test]
+    fn finds_members_by_name() {
+        let (a, b) = (package("a", "0.1.0"), package("b", "1.0.0"));
+        let members = [&a, &b];
+        assert_eq!(find_member(&members, "b").unwrap().name, "b");
+    }
+
+    #[test]
+    fn finds_members_by_name_and_version() {
+        let (old, new) = (package("a", "0.1.0"), package("a", "0.2.0"));
+        let members = [&old, &new];
+        let found = find_member(&members, "a@0.2.0").unwrap();
+        assert_eq!(found.version.to_string(), "0.2.0");
+    }
+
+    #[test]
+    fn rejects_unknown_packages() {
+        let a = package("a", "0.1.0");
+        assert_eq!(
+            find_member(&[&a], "c").unwrap_err(),
+            "package `c` not found in workspace"
+        );
+        assert!(find_member(&[&a], "a@0.2.0").is_err());
+    }
+
+    fn parse(args: &[&str]) -> ArgMatches<'static> {
+        let args: Vec<String> = ["cargo-watch"]
+            .iter()
+            .chain(args)
+            .map(|a| a.to_string())
+            .collect();
+        crate::args::try_parse(&args).unwrap()
+    }
+
+    #[test]
+    fn tells_which_commands_may_read_stdin() {
+        assert!(!may_read_stdin(&parse(&[])));
+        assert!(!may_read_stdin(&parse(&["-x", "check", "-x", "test"])));
+        assert!(may_read_stdin(&parse(&["-x", "test", "-x", "run -- a"])));
+        assert!(may_read_stdin(&parse(&["-x", "r"])));
+        assert!(may_read_stdin(&parse(&["-s", "make"])));
+        assert!(may_read_stdin(&parse(&["--on", "*.sql=migrate"])));
+        assert!(may_read_stdin(&parse(&["--", "python", "repl.py"])));
+    }
+}
@@ -0,0 +1,317 @@
+use std::{collections::HashMap, ffi::OsString, io, process::ExitStatus, time::Instant};
+
+use cargo_metadata::{MetadataCommand, PackageId};
+use log::{error, info};
+
+use crate::{
+    diagnostics::{Collector, Executable, Summary},
+    environment::Environment,
+    events::{Event, Reporter},
+    process::Supervisor,
+};
+
+/// One command of a run, started as its own process.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Step {
+    /// The command as shown to the user.
+    pub name: String,
+    /// What to give to the shell, or the program and its arguments if there is no shell.
+    pub cmd: Vec<String>,
+    /// Whether the command prints cargo's JSON messages, to be rendered and counted.
+    pub diagnostics: bool,
+    /// For a build standing in for `cargo run`, the arguments to start the
+    /// built program with once the build succeeds.
+    pub serve: Option<String>,
+}
+
+impl Step {
+    /// A command given to the shell as is.
+    pub fn new(command: String) -> Self {
+        Self {
+            name: command.clone(),
+            cmd: vec![command],
+            diagnostics: false,
+            serve: None,
+        }
+    }
+}
+
+/// How a run ended.
+#[derive(Clone, Debug)]
+pub struct Outcome {
+    pub run: u64,
+    /// The status of the last command, if it could be started.
+    pub status: Option<ExitStatus>,
+    /// Compiler errors and warnings, if any cargo command was run.
+    pub diagnostics: Option<Summary>,
+}
+
+/// Runs the steps in order, stopping at the first failure.
+///
+/// Each command gets the variables describing the changes, then its own from
+/// the environment.
+///
+/// Returns early and quietly, without an outcome, if the run is stopped by the
+/// supervisor.
+pub fn run(
+    supervisor: &Supervisor,
+    generation: u64,
+    run: u64,
+    steps: &[Step],
+    env: &Environment,
+    changes: &[(String, OsString)],
+    reporter: &Reporter,
+) -> Option<Outcome> {
+    let run_started = Instant::now();
+    reporter.emit(&Event::RunStarted {
+        run,
+        commands: steps.iter().map(|step| step.name.as_str()).collect(),
+    });
+
+    let mut last_status = None;
+    let mut failed_command = None;
+    let mut collector = None;
+    for (n, step) in steps.iter().enumerate() {
+        reporter.emit(&Event::CommandStarted {
+            run,
+            command: &step.name,
+            step: n + 1,
+            steps: steps.len(),
+        });
+
+        let mut vars = changes.to_vec();
+        vars.extend(env.for_step(step));
+
+        let started = Instant::now();
+        let status = match supervisor.spawn(generation, &step.cmd, &vars, step.diagnostics) {
+            Ok(true) => {
+                let built = match supervisor.take_stdout() {
+                    Some(output) => collector
+                        .get_or_insert_with(Collector::default)
+                        .read(output),
+                    None => Vec::new(),
+                };
+
+                let status = supervisor.wait_child();
+                if !supervisor.is_current(generation) {
+                    return None;
+                }
+
+                match status {
+                    Ok(Some(status)) => match &step.serve {
+                        Some(args) if status.success() => {
+                            match serve(
+                                supervisor, generation, run, step, &built, args, &vars, reporter,
+                            ) {
+                                Ok(true) => Some(status),
+                                Ok(false) => return None,
+                                Err(err) => {
+                                    error!(
+                                        "Failed to start the program built by '{}': {}",
+                                        step.name, err
+                                    );
+                                    None
+                                }
+                            }
+                        }
+                        Some(_) => {
+                            if supervisor.is_serving() {
+                                info!("Build failed, keeping the running program");
+                            }
+                            Some(status)
+                        }
+                        None => Some(status),
+                    },
+                    Ok(None) => return None,
+                    Err(err) => {
+                        error!("Failed to wait for '{}': {}", step.name, err);
+                        None
+                    }
+                }
+            }
+            Ok(false) => return None,
+            Err(err) => {
+                error!("Failed to start '{}': {}", step.name, err);
+                None
+            }
+        };
+
+        let success = status.is_some_and(|status| status.success());
+        reporter.emit(&Event::CommandFinished {
+            run,
+            command: &step.name,
+            step: n + 1,
+            steps: steps.len(),
+            exit_status: status.and_then(|status| status.code()),
+            success,
+            duration: started.elapsed(),
+            status,
+        });
+
+        last_status = status;
+        if !success {
+            failed_command = Some(step.name.as_str());
+            break;
+        }
+    }
+
+    let diagnostics = collector.map(|collector| collector.summary());
+    reporter.emit(&Event::RunFinished {
+        run,
+        exit_status: last_status.and_then(|status| status.code()),
+        success: failed_command.is_none(),
+        failed_command,
+        diagnostics: diagnostics.as_ref(),
+        duration: run_started.elapsed(),
+        status: last_status,
+    });
+
+    Some(Outcome {
+        run,
+        status: last_status,
+        diagnostics,
+    })
+}
+
+/// Replaces the running program, if any, with the one just built.
+#[allow(clippy::too_many_arguments)]
+fn serve(
+    supervisor: &Supervisor,
+    generation: u64,
+    run: u64,
+    step: &Step,
+    built: &[Executable],
+    args: &str,
+    env: &[(String, OsString)],
+    reporter: &Reporter,
+) -> io::Result<bool> {
+    let program = match built {
+        [program] => &program.path,
+        [] => {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "no binary was built",
+            ))
+        }
+        _ => {
+            &default_run(built)
+                .ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "several binaries were built, pick one with --bin or --example",
+                    )
+                })?
+                .path
+        }
+    };
+
+    let mut cmd = shell_escape::escape(program.to_string_lossy()).into_owned();
+    if !args.is_empty() {
+        cmd.push(' ');
+        cmd.push_str(args);
+    }
+
+    if !supervisor.serve(generation, &[cmd], env)? {
+        return Ok(false);
+    }
+
+    reporter.emit(&Event::ProgramStarted {
+        run,
+        command: &step.name,
+        program,
+    });
+    Ok(true)
+}
+
+/// Of several binaries, the one `cargo run` would have run: the `default-run`
+/// of their package.
+fn default_run(built: &[Executable]) -> Option<&Executable> {
+    let metadata = MetadataCommand::new().no_deps().exec().ok()?;
+    let defaults: HashMap<&PackageId, &str> = metadata
+        .packages
+        .iter()
+        .filter_map(|pkg| Some((&pkg.id, pkg.default_run.as_deref()?)))
+        .collect();
+    pick_default_run(built, &defaults)
+}
+
+fn pick_default_run<'b>(
+    built: &'b [Executable],
+    defaults: &HashMap<&PackageId, &str>,
+) -> Option<&'b Executable> {
+    let mut matching = built
+        .iter()
+        .filter(|exe| !exe.example && defaults.get(&exe.package_id) == Some(&exe.name.as_str()));
+    match (matching.next(), matching.next()) {
+        (Some(exe), None) => Some(exe),
+        _ => None,
+    }
+}
+
+/// The exit code, or how the command ended if it didn't exit normally.
+pub fn describe(status: ExitStatus) -> String {
+    if let Some(code) = status.code() {
+        return code.to_string();
+    }
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        if let Some(signal) = status.signal() {
+            return format!("signal {signal}");
+        }
+    }
+
+    String::from("unknown")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn id(name: &str) -> PackageId {
+        PackageId {
+            repr: format!("{name} 0.1.0 (path+file:///ws/{name})"),
+        }
+    }
+
+    fn executable(package: &str, name: &str, example: bool) -> Executable {
+        Executable {
+            package_id: id(package),
+            name: name.into(),
+            example,
+            path: format!("/ws/target/debug/{name}").into(),
+        }
+    }
+
+    #[test]
+    fn picks_default_run() {
+        let built = [
+            executable("app", "migrate", false),
+            executable("app", "server", false),
+        ];
+        let app = id("app");
+        let defaults = HashMap::from([(&app, "server")]);
+        assert_eq!(pick_default_run(&built, &defaults), Some(&built[1]));
+    }
+
+    #[test]
+    fn needs_default_run() {
+        let built = [
+            executable("app", "migrate", false),
+            executable("app", "server", false),
+        ];
+        assert_eq!(pick_default_run(&built, &HashMap::new()), None);
+    }
+
+    #[test]
+    fn leaves_out_examples_for_default_run() {
+        let built = [
+            executable("app", "migrate", false),
+            executable("app", "server", true),
+        ];
+        let app = id("app");
+        let defaults = HashMap::from([(&app, "server")]);
+        assert_eq!(pick_default_run(&built, &defaults), None);
+    }
+}
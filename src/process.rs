@@ -0,0 +1,552 @@
+use std::{
+    ffi::OsString,
+    io,
+    process::{Child, ChildStdout, Command, ExitStatus, Stdio},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread::{self, sleep, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use log::{debug, warn};
+use watchexec::Shell;
+
+/// How often to check on a running command.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// How long a command gets to exit after being asked to, before it's killed.
+const GRACE_PERIOD: Duration = Duration::from_secs(5);
+
+#[derive(Default)]
+struct Current {
+    child: Option<Child>,
+    /// Bumped whenever a run is started or stopped, so a stale run can tell it
+    /// should not start any more steps.
+    generation: u64,
+}
+
+/// Runs commands one at a time, and keeps track of the current one
+/// so it can be waited on or stopped.
+///
+/// The child is only locked briefly, so this can be shared between threads.
+pub struct Supervisor {
+    shell: Shell,
+    use_process_group: bool,
+    /// Whether cargo-watch reads the terminal itself, so commands get no input.
+    keep_stdin: AtomicBool,
+    current: Mutex<Current>,
+    run: Mutex<Option<JoinHandle<()>>>,
+    /// A program started in place of `cargo run`, which outlives the run that
+    /// started it until it's replaced.
+    server: Mutex<Option<Child>>,
+}
+
+impl Supervisor {
+    pub fn new(shell: Shell, use_process_group: bool) -> Self {
+        Self {
+            shell,
+            use_process_group,
+            keep_stdin: AtomicBool::new(false),
+            current: Mutex::default(),
+            run: Mutex::default(),
+            server: Mutex::default(),
+        }
+    }
+
+    /// Starts a run in the background. Any previous run should have been
+    /// waited on or stopped.
+    ///
+    /// The run is given the supervisor and its generation, to start commands with.
+    pub fn start<F>(self: &Arc<Self>, run: F)
+    where
+        F: FnOnce(&Supervisor, u64) + Send + 'static,
+    {
+        let generation = {
+            let mut current = self.current.lock().unwrap();
+            current.generation += 1;
+            current.generation
+        };
+
+        let supervisor = Arc::clone(self);
+        let handle = thread::spawn(move || run(&supervisor, generation));
+        *self.run.lock().unwrap() = Some(handle);
+    }
+
+    /// Whether a run is in progress right now.
+    pub fn is_running(&self) -> bool {
+        self.run
+            .lock()
+            .unwrap()
+            .as_ref()
+            .is_some_and(|handle| !handle.is_finished())
+    }
+
+    /// Waits for the current run to finish, if there is one.
+    pub fn wait(&self) {
+        let handle = self.run.lock().unwrap().take();
+        if let Some(handle) = handle {
+            handle.join().ok();
+        }
+    }
+
+    /// Stops the current run and waits for it to end.
+    ///
+    /// On Unix the running command (or its whole process group) is first asked
+    /// to terminate, and killed if it hasn't after a grace period.
+    pub fn stop(&self) -> io::Result<()> {
+        let target = {
+            let mut current = self.current.lock().unwrap();
+            current.generation += 1;
+            current.child.as_ref().map(Child::id)
+        };
+
+        if let Some(target) = target {
+            debug!("Stopping process {}", target);
+            self.terminate(target);
+
+            let started = Instant::now();
+            while self.child_is_running() {
+                if started.elapsed() > GRACE_PERIOD {
+                    warn!("Command didn't stop after {:?}, killing it", GRACE_PERIOD);
+                    self.kill(target);
+                    break;
+                }
+
+                sleep(POLL_INTERVAL);
+            }
+        }
+
+        self.wait();
+        Ok(())
+    }
+
+    /// Stops the current run, and the program started in place of `cargo run`
+    /// if there is one.
+    pub fn shutdown(&self) -> io::Result<()> {
+        self.stop()?;
+        if let Some(mut server) = self.server.lock().unwrap().take() {
+            debug!("Stopping program {}", server.id());
+            self.shut_down(&mut server);
+            #[cfg(unix)]
+            signals::set_server(0);
+        }
+
+        Ok(())
+    }
+
+    /// Keeps standard input for cargo-watch: commands started from now on are
+    /// given none, instead of sharing the terminal.
+    pub(crate) fn keep_stdin(&self) {
+        self.keep_stdin.store(true, Ordering::SeqCst);
+    }
+
+    /// Whether the run started as `generation` is still the current one.
+    pub(crate) fn is_current(&self, generation: u64) -> bool {
+        self.current.lock().unwrap().generation == generation
+    }
+
+    /// Starts one command of a run, unless the run has been stopped.
+    ///
+    /// With a shell, the command is joined with spaces and given to the shell;
+    /// without one, the first element is the program and the rest are its arguments.
+    /// If `capture` is set, its stdout is piped, to be read with [`take_stdout`](Self::take_stdout).
+    pub(crate) fn spawn(
+        &self,
+        generation: u64,
+        cmd: &[String],
+        env: &[(String, OsString)],
+        capture: bool,
+    ) -> io::Result<bool> {
+        let mut command = self.command(cmd, env)?;
+        if capture {
+            command.stdout(Stdio::piped());
+        }
+
+        #[cfg(unix)]
+        if self.use_process_group {
+            use std::os::unix::process::CommandExt;
+            command.process_group(0);
+        }
+
+        // hold the lock so the run can't be stopped between checking and spawning
+        let mut current = self.current.lock().unwrap();
+        if current.generation != generation {
+            return Ok(false);
+        }
+
+        debug!("Spawning {:?}", command);
+        let child = command.spawn()?;
+
+        #[cfg(unix)]
+        signals::set_current(self.signal_target(child.id()));
+
+        current.child = Some(child);
+        Ok(true)
+    }
+
+    /// Starts a program which keeps running after the run, stopping the one
+    /// started before, unless the run has been stopped.
+    pub(crate) fn serve(
+        &self,
+        generation: u64,
+        cmd: &[String],
+        env: &[(String, OsString)],
+    ) -> io::Result<bool> {
+        let mut command = self.command(cmd, env)?;
+
+        #[cfg(unix)]
+        if self.use_process_group {
+            use std::os::unix::process::CommandExt;
+            command.process_group(0);
+        }
+
+        let mut server = self.server.lock().unwrap();
+        if !self.is_current(generation) {
+            return Ok(false);
+        }
+
+        if let Some(mut old) = server.take() {
+            debug!("Stopping program {}", old.id());
+            self.shut_down(&mut old);
+            #[cfg(unix)]
+            signals::set_server(0);
+        }
+
+        // hold the lock so the run can't be stopped between checking and spawning
+        let current = self.current.lock().unwrap();
+        if current.generation != generation {
+            return Ok(false);
+        }
+
+        debug!("Spawning {:?}", command);
+        let child = command.spawn()?;
+
+        #[cfg(unix)]
+        signals::set_server(self.signal_target(child.id()));
+
+        *server = Some(child);
+        Ok(true)
+    }
+
+    fn command(&self, cmd: &[String], env: &[(String, OsString)]) -> io::Result<Command> {
+        let mut command = to_command(&self.shell, cmd)?;
+        command.envs(env.iter().map(|(k, v)| (k, v)));
+        if self.keep_stdin.load(Ordering::SeqCst) {
+            command.stdin(Stdio::null());
+        }
+
+        Ok(command)
+    }
+
+    /// Whether a program started in place of `cargo run` is still running.
+    pub(crate) fn is_serving(&self) -> bool {
+        let mut server = self.server.lock().unwrap();
+        matches!(server.as_mut().map(Child::try_wait), Some(Ok(None)))
+    }
+
+    /// The piped stdout of the running command, if it was captured.
+    pub(crate) fn take_stdout(&self) -> Option<ChildStdout> {
+        self.current.lock().unwrap().child.as_mut()?.stdout.take()
+    }
+
+    /// Waits for the running command to finish.
+    pub(crate) fn wait_child(&self) -> io::Result<Option<ExitStatus>> {
+        loop {
+            {
+                let mut current = self.current.lock().unwrap();
+                match current.child.as_mut() {
+                    None => return Ok(None),
+                    Some(child) => {
+                        if let Some(status) = child.try_wait()? {
+                            current.child = None;
+                            #[cfg(unix)]
+                            signals::set_current(0);
+                            return Ok(Some(status));
+                        }
+                    }
+                }
+            }
+
+            sleep(POLL_INTERVAL);
+        }
+    }
+
+    fn child_is_running(&self) -> bool {
+        let mut current = self.current.lock().unwrap();
+        match current.child.as_mut().map(Child::try_wait) {
+            Some(Ok(None)) => true,
+            Some(Ok(Some(_))) | Some(Err(_)) | None => false,
+        }
+    }
+
+    /// Asks a program to stop, and kills it if it hasn't after a grace period.
+    fn shut_down(&self, child: &mut Child) {
+        #[cfg(unix)]
+        {
+            self.send(child.id(), libc::SIGTERM);
+
+            let started = Instant::now();
+            while let Ok(None) = child.try_wait() {
+                if started.elapsed() > GRACE_PERIOD {
+                    warn!("Program didn't stop after {:?}, killing it", GRACE_PERIOD);
+                    self.send(child.id(), libc::SIGKILL);
+                    break;
+                }
+
+                sleep(POLL_INTERVAL);
+            }
+        }
+
+        #[cfg(not(unix))]
+        child.kill().ok();
+
+        child.wait().ok();
+    }
+
+    #[cfg(unix)]
+    fn terminate(&self, pid: u32) {
+        self.send(pid, libc::SIGTERM);
+    }
+
+    #[cfg(not(unix))]
+    fn terminate(&self, pid: u32) {
+        self.kill(pid);
+    }
+
+    #[cfg(unix)]
+    fn kill(&self, pid: u32) {
+        self.send(pid, libc::SIGKILL);
+    }
+
+    #[cfg(not(unix))]
+    fn kill(&self, _pid: u32) {
+        // without process groups, only the direct child can be stopped
+        if let Some(child) = self.current.lock().unwrap().child.as_mut() {
+            child.kill().ok();
+        }
+    }
+
+    /// What to signal to reach a command: its process group, if it has one.
+    #[cfg(unix)]
+    fn signal_target(&self, pid: u32) -> libc::pid_t {
+        let pid = pid as libc::pid_t;
+        if self.use_process_group {
+            -pid
+        } else {
+            pid
+        }
+    }
+
+    #[cfg(unix)]
+    fn send(&self, pid: u32, signal: libc::c_int) {
+        let target = self.signal_target(pid);
+
+        // SAFETY: kill(2) has no memory safety concerns
+        if unsafe { libc::kill(target, signal) } != 0 {
+            debug!(
+                "Failed to signal process {}: {}",
+                target,
+                io::Error::last_os_error()
+            );
+        }
+    }
+}
+
+impl Drop for Supervisor {
+    fn drop(&mut self) {
+        if let Some(mut server) = self.server.lock().unwrap().take() {
+            self.shut_down(&mut server);
+        }
+    }
+}
+
+fn to_command(shell: &Shell, cmd: &[String]) -> io::Result<Command> {
+    let joined = cmd.join(" ");
+    Ok(match shell {
+        Shell::None => {
+            let (program, args) = cmd
+                .split_first()
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "empty command"))?;
+            let mut command = Command::new(program);
+            command.args(args);
+            command
+        }
+        Shell::Unix(name) => {
+            let mut command = Command::new(name);
+            command.arg("-c").arg(joined);
+            command
+        }
+        Shell::Powershell => {
+            let mut command = Command::new(if cfg!(windows) {
+                "powershell.exe"
+            } else {
+                "pwsh"
+            });
+            command.arg("-Command").arg(joined);
+            command
+        }
+        #[cfg(windows)]
+        Shell::Cmd => {
+            use std::os::windows::process::CommandExt;
+            let mut command = Command::new("cmd.exe");
+            command.arg("/C").raw_arg(joined);
+            command
+        }
+    })
+}
+
+/// Forwards termination signals to the running command before exiting.
+///
+/// Commands run in their own process group don't get the terminal's Ctrl-C,
+/// so without this they would outlive cargo-watch.
+#[cfg(unix)]
+pub mod signals {
+    use std::sync::atomic::{AtomicI32, Ordering};
+
+    /// The pid (or negated process group id) of the running command, or 0.
+    static CURRENT: AtomicI32 = AtomicI32::new(0);
+
+    /// The same for the program started in place of `cargo run`, or 0.
+    static SERVER: AtomicI32 = AtomicI32::new(0);
+
+    pub(super) fn set_current(target: i32) {
+        CURRENT.store(target, Ordering::SeqCst);
+    }
+
+    pub(super) fn set_server(target: i32) {
+        SERVER.store(target, Ordering::SeqCst);
+    }
+
+    extern "C" fn on_terminate(signal: libc::c_int) {
+        // SAFETY: only async-signal-safe functions are called here
+        unsafe {
+            for target in [
+                CURRENT.load(Ordering::SeqCst),
+                SERVER.load(Ordering::SeqCst),
+            ] {
+                if target != 0 {
+                    libc::kill(target, libc::SIGTERM);
+                }
+            }
+
+            crate::keys::terminal::restore();
+            crate::control::remove();
+            crate::waker::remove();
+            libc::signal(signal, libc::SIG_DFL);
+            libc::raise(signal);
+        }
+    }
+
+    pub fn install() {
+        let handler = on_terminate as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        for signal in [libc::SIGINT, libc::SIGTERM, libc::SIGHUP] {
+            // SAFETY: the handler only does async-signal-safe things
+            unsafe {
+                libc::signal(signal, handler);
+            }
+        }
+    }
+}
+
+#[cfg(not(unix))]
+pub mod signals {
+    /// Console control events are delivered to every process on the console,
+    /// so the command gets them directly.
+    pub fn install() {}
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::sync::mpsc;
+
+    use super::*;
+
+    fn supervisor() -> Arc<Supervisor> {
+        Arc::new(Supervisor::new(Shell::default(), true))
+    }
+
+    fn cmd(command: &str) -> Vec<String> {
+        vec![command.into()]
+    }
+
+    #[test]
+    fn runs_commands_in_the_background() {
+        let supervisor = supervisor();
+        let (tx, rx) = mpsc::channel();
+        supervisor.start(move |supervisor, generation| {
+            supervisor
+                .spawn(generation, &cmd("exit 3"), &[], false)
+                .unwrap();
+            tx.send(supervisor.wait_child().unwrap()).unwrap();
+        });
+
+        let status = rx.recv_timeout(Duration::from_secs(10)).unwrap();
+        assert_eq!(status.and_then(|s| s.code()), Some(3));
+        supervisor.wait();
+        assert!(!supervisor.is_running());
+    }
+
+    #[test]
+    fn stops_running_commands() {
+        let supervisor = supervisor();
+        let (tx, rx) = mpsc::channel();
+        supervisor.start(move |supervisor, generation| {
+            supervisor
+                .spawn(generation, &cmd("sleep 30"), &[], false)
+                .unwrap();
+            tx.send(true).unwrap();
+            supervisor.wait_child().unwrap();
+
+            // stopped runs don't start more commands
+            let started = supervisor.spawn(generation, &cmd("sleep 30"), &[], false);
+            tx.send(started.unwrap()).unwrap();
+        });
+
+        assert!(rx.recv_timeout(Duration::from_secs(10)).unwrap());
+        assert!(supervisor.is_running());
+
+        let started = Instant::now();
+        supervisor.stop().unwrap();
+        assert!(started.elapsed() < GRACE_PERIOD);
+        assert!(!supervisor.is_running());
+        assert!(!supervisor.child_is_running());
+        assert!(!rx.recv_timeout(Duration::from_secs(10)).unwrap());
+    }
+
+    #[test]
+    fn replaces_programs() {
+        let supervisor = supervisor();
+        let (tx, rx) = mpsc::channel();
+        supervisor.start(move |supervisor, generation| {
+            assert!(supervisor.serve(generation, &cmd("sleep 30"), &[]).unwrap());
+            let first = supervisor.server.lock().unwrap().as_ref().map(Child::id);
+            assert!(supervisor.serve(generation, &cmd("sleep 30"), &[]).unwrap());
+            let second = supervisor.server.lock().unwrap().as_ref().map(Child::id);
+            tx.send((first, second)).unwrap();
+        });
+
+        let (first, second) = rx.recv_timeout(Duration::from_secs(10)).unwrap();
+        supervisor.wait();
+        assert_ne!(first, second);
+        assert!(supervisor.is_serving());
+
+        supervisor.shutdown().unwrap();
+        assert!(!supervisor.is_serving());
+    }
+
+    #[test]
+    fn runs_without_a_shell() {
+        let supervisor = Arc::new(Supervisor::new(Shell::None, false));
+        let (tx, rx) = mpsc::channel();
+        supervisor.start(move |supervisor, generation| {
+            let args = vec!["sh".into(), "-c".into(), "exit 4".into()];
+            supervisor.spawn(generation, &args, &[], false).unwrap();
+            tx.send(supervisor.wait_child().unwrap()).unwrap();
+        });
+
+        let status = rx.recv_timeout(Duration::from_secs(10)).unwrap();
+        assert_eq!(status.and_then(|s| s.code()), Some(4));
+    }
+}
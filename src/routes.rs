@@ -0,0 +1,90 @@
+use std::{
+    env,
+    path::{Path, PathBuf},
+};
+
+use clap::{values_t, ArgMatches};
+use globset::{GlobBuilder, GlobMatcher};
+
+/// A rule from `--on`: a command to run when a changed path matches the pattern.
+struct Rule {
+    matcher: GlobMatcher,
+    command: String,
+}
+
+/// Commands to run for changes to some files only, from `--on PATTERN=COMMAND`.
+///
+/// Patterns are globs relative to the crate root, like `templates/**`, or, if
+/// they have no `/`, are matched against file names anywhere, like `*.sql`.
+pub struct Routes {
+    root: PathBuf,
+    rules: Vec<Rule>,
+}
+
+impl Routes {
+    pub fn from_matches(matches: &ArgMatches) -> Result<Self, String> {
+        let mut rules = Vec::new();
+        if matches.is_present("on") {
+            for rule in values_t!(matches, "on", String).map_err(|e| e.message)? {
+                let (pattern, command) = rule
+                    .split_once('=')
+                    .filter(|(pattern, command)| {
+                        !pattern.trim().is_empty() && !command.trim().is_empty()
+                    })
+                    .ok_or_else(|| {
+                        format!("Malformed rule '{rule}', expected --on PATTERN=COMMAND")
+                    })?;
+
+                let pattern = pattern.trim();
+                let glob = if pattern.contains('/') {
+                    pattern.trim_start_matches('/').to_string()
+                } else {
+                    format!("**/{pattern}")
+                };
+                let matcher = GlobBuilder::new(&glob)
+                    .literal_separator(true)
+                    .build()
+                    .map_err(|e| format!("Invalid pattern '{pattern}': {e}"))?
+                    .compile_matcher();
+
+                rules.push(Rule {
+                    matcher,
+                    command: command.trim().to_string(),
+                });
+            }
+        }
+
+        Ok(Self {
+            root: env::current_dir()
+                .and_then(|dir| dir.canonicalize())
+                .unwrap_or_else(|_| ".".into()),
+            rules,
+        })
+    }
+
+    /// Every command, in the order given, once each.
+    pub fn commands(&self) -> Vec<&str> {
+        self.select(|_| true)
+    }
+
+    /// The commands whose patterns match any of the changed paths, in the
+    /// order given, once each.
+    pub fn matching(&self, paths: &[&Path]) -> Vec<&str> {
+        let paths: Vec<&Path> = paths
+            .iter()
+            .map(|path| path.strip_prefix(&self.root).unwrap_or(path))
+            .collect();
+
+        self.select(|rule| paths.iter().any(|path| rule.matcher.is_match(path)))
+    }
+
+    fn select(&self, filter: impl Fn(&Rule) -> bool) -> Vec<&str> {
+        let mut commands: Vec<&str> = Vec::new();
+        for rule in self.rules.iter().filter(|rule| filter(rule)) {
+            if !commands.contains(&rule.command.as_str()) {
+                commands.push(&rule.command);
+            }
+        }
+        commands
+    }
+}
@@ -0,0 +1,92 @@
+//! Waking the watch loop from other threads.
+//!
+//! Watchexec only hands control back when it sees changes, so a directory of
+//! cargo-watch's own is watched along with the others, and a file in it is
+//! written to when the loop should look at things again, like when a run ended
+//! with build scripts declaring other paths to watch, or a git operation ended.
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process,
+    sync::OnceLock,
+};
+#[cfg(unix)]
+use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+use log::{debug, warn};
+
+/// The directory, created on first use, if that worked.
+static DIR: OnceLock<Option<Dir>> = OnceLock::new();
+
+const FILE_NAME: &str = "wake";
+
+struct Dir {
+    path: PathBuf,
+    /// The directory and the file in it, to remove them when terminating.
+    #[cfg(unix)]
+    c_path: CString,
+    #[cfg(unix)]
+    c_file: CString,
+}
+
+/// The directory to watch, made the first time it's asked for.
+pub fn dir() -> Option<&'static Path> {
+    DIR.get_or_init(|| {
+        let path = env::temp_dir().join(format!("cargo-watch-{}", process::id()));
+        let path = fs::create_dir_all(&path)
+            .and_then(|()| path.canonicalize())
+            .map_err(|err| warn!("Failed to create {}: {}", path.display(), err))
+            .ok()?;
+        debug!("Waking the watcher through {:?}", path);
+        Some(Dir {
+            #[cfg(unix)]
+            c_path: c_string(&path),
+            #[cfg(unix)]
+            c_file: c_string(&path.join(FILE_NAME)),
+            path,
+        })
+    })
+    .as_ref()
+    .map(|dir| dir.path.as_path())
+}
+
+/// Wakes the watch loop, if it's watching the directory.
+pub fn wake() {
+    if let Some(Some(dir)) = DIR.get() {
+        fs::write(dir.path.join(FILE_NAME), "").unwrap_or_else(|err| {
+            warn!("Failed to wake the watcher: {}", err);
+        });
+    }
+}
+
+/// Whether a change is from waking the loop rather than to a watched file.
+pub fn is_wake(path: &Path) -> bool {
+    matches!(DIR.get(), Some(Some(dir)) if path.starts_with(&dir.path))
+}
+
+/// Removes the directory, if it was made.
+///
+/// This is async-signal-safe, so it can be called when terminating.
+pub fn remove() {
+    if let Some(Some(dir)) = DIR.get() {
+        #[cfg(unix)]
+        // SAFETY: unlink and rmdir are async-signal-safe and the paths are
+        // valid C strings
+        unsafe {
+            libc::unlink(dir.c_file.as_ptr());
+            libc::rmdir(dir.c_path.as_ptr());
+        }
+
+        #[cfg(not(unix))]
+        {
+            fs::remove_file(dir.path.join(FILE_NAME)).ok();
+            fs::remove_dir(&dir.path).ok();
+        }
+    }
+}
+
+#[cfg(unix)]
+fn c_string(path: &Path) -> CString {
+    CString::new(path.as_os_str().as_bytes()).unwrap_or_default()
+}
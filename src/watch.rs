@@ -1,84 +1,936 @@
+use std::{
+    collections::BTreeMap,
+    env,
+    ffi::{OsStr, OsString},
+    fs::{self, File},
+    io::{self, BufWriter, Write as _},
+    path::{Component, Path, PathBuf, MAIN_SEPARATOR_STR},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+use cargo_metadata::Metadata;
+use clap::ArgMatches;
+use log::{debug, error, info, warn};
 use watchexec::{
     config::Config,
     error::Result,
     pathop::PathOp,
-    run::{ExecHandler, Handler},
+    run::{self, Handler, OnBusyUpdate},
+};
+
+use crate::{
+    buildscript::{BuildScripts, Declared},
+    contents::Contents,
+    depinfo::DepInfo,
+    environment::Environment,
+    events::{Event, Reporter, RunObserver},
+    git::Repo,
+    options::{self, Packages},
+    pipeline::{self, describe, Outcome, Step},
+    process::Supervisor,
+    routes::Routes,
+    waker,
+    workspace::Workspace,
 };
 
 pub struct CwHandler {
-    cmd: String,
+    args: Config,
+    matches: ArgMatches<'static>,
     once: bool,
-    quiet: bool,
-    notify: bool,
-    inner: ExecHandler,
+    reporter: Arc<Reporter>,
+    /// How many runs have been started, to number them in events.
+    runs: AtomicU64,
+    /// How the last run to finish ended.
+    last_run: Arc<Mutex<Option<Outcome>>>,
+    /// Held while stopping and starting the commands, as runs are asked for
+    /// from the watcher, keys, the control socket and the env file poller.
+    starting: Mutex<()>,
+    /// Whether changes are being ignored, as asked for with the `p` key.
+    paused: AtomicBool,
+    /// The commands for the first run and reruns not caused by changes, which
+    /// are all the `--on` commands if no others were given.
+    steps: Vec<Step>,
+    /// Commands to run instead when the changes match their patterns.
+    routes: Routes,
+    /// Variables for the commands, from the options, replaced when the
+    /// `--env-file` files change.
+    env: Mutex<Arc<Environment>>,
+    /// Without `--watch`, the metadata the paths to watch were last worked
+    /// out from, loaded again when the manifests change.
+    metadata: Mutex<Option<Metadata>>,
+    /// With `--affected`, the local packages, reloaded with the manifests.
+    workspace: Mutex<Option<Workspace>>,
+    /// The paths to watch, worked out again when the manifests change.
+    watches: Mutex<Vec<PathBuf>>,
+    /// Whether to watch again with new paths when watching stops.
+    rewatch: AtomicBool,
+    /// Whether watching has started again, after the first run.
+    rewatched: AtomicBool,
+    /// Without `--watch`, where to read what build scripts depend on,
+    /// reloaded with the manifests.
+    build_scripts: Mutex<Option<Arc<BuildScripts>>>,
+    /// What build scripts declared with `rerun-if-changed` and
+    /// `rerun-if-env-changed`, read again after each run.
+    declared: Arc<Mutex<Declared>>,
+    /// Whether the declared paths changed since the paths to watch were last
+    /// worked out.
+    declared_changed: Arc<AtomicBool>,
+    /// Whether the watch loop is to be woken from other threads.
+    wakes: bool,
+    /// With `--dep-info`, the files the builds depend on.
+    dep_info: Mutex<Option<DepInfo>>,
+    /// With `--skip-unchanged`, the hashes of the watched files.
+    contents: Option<Contents>,
+    /// The git repository, to hold runs while git rewrites files.
+    git: Option<Arc<Repo>>,
+    /// The changes seen since a git operation started, while runs are held.
+    held: Mutex<Option<Vec<PathOp>>>,
+    /// Whether the git operation runs are held for is over.
+    git_finished: Arc<AtomicBool>,
+    process: Arc<Supervisor>,
 }
 
 impl Handler for CwHandler {
     fn args(&self) -> Config {
-        self.inner.args()
+        let mut args = self.args.clone();
+        args.paths = self.watches.lock().unwrap().clone();
+        if let Some(dir) = self.wakes.then(waker::dir).flatten() {
+            args.paths.push(dir.to_path_buf());
+            if !args.filters.is_empty() {
+                args.filters
+                    .push(format!("{}{}*", dir.display(), MAIN_SEPARATOR_STR));
+            }
+        }
+        if self.rewatched.load(Ordering::SeqCst) {
+            args.run_initially = false;
+        }
+        args
     }
 
     fn on_manual(&self) -> Result<bool> {
         if self.once {
             Ok(true)
         } else {
-            self.start();
-            self.inner.on_manual()
+            self.run(self.steps.clone(), &[])
         }
     }
 
     fn on_update(&self, ops: &[PathOp]) -> Result<bool> {
-        self.start();
-        self.inner.on_update(ops).map(|o| {
-            if self.notify {
-                notify_rust::Notification::new()
-                    .summary("Cargo Watch observed a change")
-                    .body("Cargo Watch has seen a change, the command may have restarted.")
-                    .show()
-                    .map(drop)
-                    .unwrap_or_else(|err| {
-                        log::warn!("Failed to send desktop notification: {}", err);
-                    });
-            }
+        if self.is_paused() {
+            debug!("Paused, ignoring changes");
+            return Ok(true);
+        }
 
-            o
-        })
+        // being woken is only a chance to look at things again
+        let ops: Vec<PathOp> = ops
+            .iter()
+            .filter(|op| !waker::is_wake(&op.path))
+            .cloned()
+            .collect();
+
+        // hold runs while git rewrites files, and run once when it's done
+        let ops = match self.hold_for_git(&ops) {
+            Some(ops) => ops,
+            None => return Ok(true),
+        };
+        let ops = ops.as_slice();
+
+        // stop watching to start again with the new paths, once the run is started
+        let rewatch = self.reload_metadata(ops);
+        let keep_going = if ops.is_empty() {
+            true
+        } else {
+            self.on_changes(ops)?
+        };
+        if keep_going && rewatch {
+            self.rewatch.store(true, Ordering::SeqCst);
+            return Ok(false);
+        }
+
+        Ok(keep_going)
     }
 }
 
 impl CwHandler {
-    pub fn new(mut args: Config, quiet: bool, notify: bool, trailing: bool) -> Result<Self> {
-        let cmd = if trailing {
-            args.cmd[0].clone()
+    /// Sets up the handler for the parsed arguments, or returns why they don't
+    /// make sense.
+    pub(crate) fn new(
+        args: Config,
+        matches: ArgMatches<'static>,
+        reporter: Reporter,
+    ) -> Result<Self> {
+        let watches = args.paths.clone();
+        let contents = if matches.is_present("skip-unchanged") {
+            Some(Contents::default())
         } else {
-            let cmd = args.cmd.join(" && ");
-            let mut final_cmd = cmd.clone();
-            if !quiet {
-                #[cfg(unix)]
-                final_cmd.push_str(r#"; echo "[Finished running. Exit status: $?]""#);
-                #[cfg(windows)]
-                final_cmd.push_str(r#" & echo [Finished running. Exit status: %ERRORLEVEL%]"#);
-                #[cfg(not(any(unix, windows)))]
-                final_cmd.push_str(r#" ; echo "[Finished running]""#);
-                // ^ could be wrong depending on the platform, to be fixed on demand
-            }
-
-            args.cmd = vec![final_cmd];
-            cmd
+            None
         };
+        let packages = Packages::from_matches(&matches)?;
+        let routes = Routes::from_matches(&matches)?;
+        let mut steps = options::get_steps(&matches, &packages)?;
+        if steps.is_empty() {
+            steps = options::route_steps(&matches, &packages, &routes.commands());
+        }
 
-        Ok(Self {
+        let env = Environment::from_matches(&matches)?;
+        let git = if matches.is_present("no-git-wait") {
+            None
+        } else {
+            Repo::find().map(Arc::new)
+        };
+        let wakes = !args.once && (git.is_some() || !matches.is_present("watch"));
+        let handler = Self {
             once: args.once,
-            cmd,
-            inner: ExecHandler::new(args)?,
-            quiet,
-            notify,
-        })
+            process: Arc::new(Supervisor::new(args.shell.clone(), args.use_process_group)),
+            args,
+            matches,
+            reporter: Arc::new(reporter),
+            runs: AtomicU64::new(0),
+            last_run: Arc::default(),
+            starting: Mutex::default(),
+            paused: AtomicBool::new(false),
+            steps,
+            routes,
+            env: Mutex::new(Arc::new(env)),
+            metadata: Mutex::default(),
+            workspace: Mutex::default(),
+            watches: Mutex::new(watches),
+            rewatch: AtomicBool::new(false),
+            rewatched: AtomicBool::new(false),
+            build_scripts: Mutex::default(),
+            declared: Arc::default(),
+            declared_changed: Arc::default(),
+            wakes,
+            dep_info: Mutex::default(),
+            contents,
+            git,
+            held: Mutex::default(),
+            git_finished: Arc::default(),
+        };
+
+        if handler.needs_metadata() {
+            match options::load_metadata() {
+                Ok(metadata) => handler.load_packages(metadata),
+                Err(err) => eprintln!("Finding local deps failed: {}", err),
+            }
+        }
+
+        match handler.local_watches() {
+            Some(Ok(watches)) => *handler.watches.lock().unwrap() = watches,
+            Some(Err(err)) => eprintln!("Finding local deps failed: {}", err),
+            None => {}
+        }
+        debug!("Watches: {:?}", handler.watches.lock().unwrap());
+
+        Ok(handler)
+    }
+
+    /// Watches for changes and runs the commands, until the process exits.
+    ///
+    /// Watching starts again with new paths when the local packages change.
+    pub fn watch(&self) -> Result<()> {
+        let result = loop {
+            if let Some(contents) = &self.contents {
+                contents.scan(&self.args());
+            }
+            if let Err(err) = run::watch(self) {
+                break Err(err);
+            }
+            if !self.rewatch.swap(false, Ordering::SeqCst) {
+                break Ok(());
+            }
+
+            self.rewatched.store(true, Ordering::SeqCst);
+        };
+
+        waker::remove();
+        result
+    }
+
+    /// Reads the `-E`, `--env-file`, `-B` and `-L` variables again. If they
+    /// changed, returns those of the changed variables which build scripts
+    /// declared with `rerun-if-env-changed`.
+    ///
+    /// The previous variables are kept if a file can't be read.
+    pub(crate) fn reload_env(&self) -> std::result::Result<Option<Vec<String>>, String> {
+        let env = Environment::from_matches(&self.matches)?;
+        let mut current = self.env.lock().unwrap();
+        if **current == env {
+            return Ok(None);
+        }
+
+        let changed = current.changed_names(&env);
+        *current = Arc::new(env);
+        let declared = self.declared.lock().unwrap();
+        Ok(Some(
+            changed
+                .into_iter()
+                .filter(|name| declared.env.contains(name))
+                .collect(),
+        ))
+    }
+
+    /// Stops the commands if they're running, and runs them again.
+    pub(crate) fn rerun(&self) -> Result<()> {
+        let _starting = self.starting.lock().unwrap();
+        self.process.stop()?;
+        self.start(self.steps.clone(), &[]);
+        Ok(())
+    }
+
+    /// Stops the commands, including a program left running by `--keep-running`.
+    pub(crate) fn kill(&self) -> Result<()> {
+        self.process.shutdown()?;
+        Ok(())
+    }
+
+    /// Gives the commands no standard input, as keys are read from it.
+    pub(crate) fn keep_stdin(&self) {
+        self.process.keep_stdin();
+    }
+
+    pub(crate) fn clear_screen(&self) {
+        clearscreen::clear().unwrap_or_else(|err| {
+            warn!("Failed to clear screen: {}", err);
+        });
+    }
+
+    /// Whether the commands are running right now.
+    pub(crate) fn is_running(&self) -> bool {
+        self.process.is_running()
+    }
+
+    /// How the last run to finish ended, if any has.
+    pub(crate) fn last_run(&self) -> Option<Outcome> {
+        self.last_run.lock().unwrap().clone()
     }
 
-    fn start(&self) {
-        if !self.quiet {
-            println!("[Running '{}']", self.cmd);
+    pub(crate) fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+    }
+
+    /// Stops or starts acting on changes. Changes made while paused are dropped.
+    pub(crate) fn set_paused(&self, paused: bool) {
+        if self.paused.swap(paused, Ordering::SeqCst) != paused {
+            self.reporter.emit(&if paused {
+                Event::Paused
+            } else {
+                Event::Resumed
+            });
+        }
+    }
+
+    /// Workspace members affected by the changes, if running only for those.
+    fn affected(&self, ops: &[PathOp]) -> Option<Vec<String>> {
+        self.workspace
+            .lock()
+            .unwrap()
+            .as_ref()?
+            .affected(ops.iter().map(|op| op.path.as_path()))
+            .filter(|packages| !packages.is_empty())
+    }
+
+    /// With `--dep-info`, the changes to files which are part of the build,
+    /// as of the latest dep-info files.
+    fn in_build(&self, ops: &[PathOp]) -> Option<Vec<PathOp>> {
+        let mut dep_info = self.dep_info.lock().unwrap();
+        let dep_info = dep_info.as_mut()?;
+        dep_info.refresh();
+        Some(
+            ops.iter()
+                .filter(|op| dep_info.contains(&op.path))
+                .cloned()
+                .collect(),
+        )
+    }
+
+    /// With `--skip-unchanged`, the changes to files whose contents changed.
+    fn content_changed(&self, ops: &[PathOp]) -> Option<Vec<PathOp>> {
+        let contents = self.contents.as_ref()?;
+        Some(
+            ops.iter()
+                .filter(|op| contents.changed(&op.path))
+                .cloned()
+                .collect(),
+        )
+    }
+
+    /// Holds the changes while a git operation is in progress, or `HEAD` just
+    /// moved, returning `None`. Once the operation is over, a background
+    /// thread wakes the watcher, and all the changes seen since it started are
+    /// returned at once, to run for them once.
+    fn hold_for_git(&self, ops: &[PathOp]) -> Option<Vec<PathOp>> {
+        let git = match &self.git {
+            Some(git) => git,
+            None => return Some(ops.to_vec()),
+        };
+
+        let mut held = self.held.lock().unwrap();
+        if held.is_none() {
+            let operation = git.operation();
+            let head_moved = git.head_moved();
+            if operation.is_none() && !head_moved {
+                return Some(ops.to_vec());
+            }
+
+            info!(
+                "Waiting for the {} to finish",
+                operation.unwrap_or("git checkout")
+            );
+            self.git_finished.store(false, Ordering::SeqCst);
+            let git = Arc::clone(git);
+            let finished = Arc::clone(&self.git_finished);
+            let debounce = self.args.debounce;
+            thread::spawn(move || {
+                while git.operation().is_some() {
+                    thread::sleep(GIT_POLL_INTERVAL);
+                }
+
+                // the last files git wrote may still be coming in
+                thread::sleep(debounce);
+                finished.store(true, Ordering::SeqCst);
+                waker::wake();
+            });
+        }
+
+        let all = held.get_or_insert_with(Vec::new);
+        for op in ops {
+            if !all.contains(op) {
+                all.push(op.clone());
+            }
+        }
+        if !self.git_finished.load(Ordering::SeqCst) {
+            debug!("Holding {} changes for the git operation", all.len());
+            return None;
+        }
+
+        // the next changes start again, so HEAD moving since is seen then
+        git.head_moved();
+        held.take()
+    }
+
+    /// When a manifest or cargo's config changed, finds the local packages
+    /// again, and returns whether the paths to watch changed. The same goes
+    /// when a run ended with build scripts declaring other paths.
+    ///
+    /// If that fails, as a manifest is being edited, things are left as they were.
+    fn reload_metadata(&self, ops: &[PathOp]) -> bool {
+        if self.once {
+            return false;
+        }
+
+        let manifests = ops.iter().any(|op| is_manifest(&op.path));
+        let declared = self.declared_changed.swap(false, Ordering::SeqCst);
+        if !manifests && !declared {
+            return false;
         }
+
+        if manifests && self.needs_metadata() {
+            match options::load_metadata() {
+                Ok(metadata) => self.load_packages(metadata),
+                Err(err) => {
+                    warn!(
+                        "Finding local deps failed, watching the same paths: {}",
+                        err
+                    );
+                    return false;
+                }
+            }
+        }
+
+        let watches = match self.local_watches() {
+            Some(Ok(watches)) => watches,
+            Some(Err(err)) => {
+                warn!(
+                    "Finding local deps failed, watching the same paths: {}",
+                    err
+                );
+                return false;
+            }
+            None => return false,
+        };
+
+        let mut current = self.watches.lock().unwrap();
+        let added = describe_paths(watches.iter().filter(|path| !current.contains(path)));
+        let removed = describe_paths(current.iter().filter(|path| !watches.contains(path)));
+        if added.is_empty() && removed.is_empty() {
+            return false;
+        }
+
+        if !self.matches.is_present("log:quiet") {
+            eprintln!(
+                "[Watched paths changed, added: {}; removed: {}]",
+                if added.is_empty() { "none" } else { &added },
+                if removed.is_empty() { "none" } else { &removed },
+            );
+        }
+        *current = watches;
+        true
+    }
+
+    /// Whether anything is worked out from `cargo metadata`: the paths to
+    /// watch without `--watch`, `--affected`, or `--dep-info`.
+    fn needs_metadata(&self) -> bool {
+        !self.matches.is_present("watch")
+            || self.matches.is_present("affected")
+            || self.matches.is_present("dep-info")
+    }
+
+    /// Works out what depends on the local packages from fresh metadata.
+    fn load_packages(&self, metadata: Metadata) {
+        if self.matches.is_present("affected") {
+            *self.workspace.lock().unwrap() = Some(Workspace::from_metadata(&metadata));
+        }
+
+        if self.matches.is_present("dep-info") {
+            *self.dep_info.lock().unwrap() = Some(DepInfo::from_metadata(&metadata));
+        }
+
+        if self.matches.is_present("watch") {
+            return;
+        }
+
+        if !self.once {
+            let scripts = BuildScripts::from_metadata(&metadata);
+            *self.declared.lock().unwrap() = scripts.declared();
+            *self.build_scripts.lock().unwrap() = Some(Arc::new(scripts));
+        }
+        *self.metadata.lock().unwrap() = Some(metadata);
+    }
+
+    /// Without `--watch`, the paths to watch as of the last metadata loaded.
+    fn local_watches(&self) -> Option<std::result::Result<Vec<PathBuf>, String>> {
+        let metadata = self.metadata.lock().unwrap();
+        let declared = self.declared.lock().unwrap();
+        Some(options::get_watches(
+            &self.matches,
+            metadata.as_ref()?,
+            &declared.paths,
+        ))
+    }
+
+    /// The `--env-file` files which exist, as full paths like those of changes.
+    fn env_files(&self) -> Vec<PathBuf> {
+        self.matches
+            .values_of("env-files")
+            .into_iter()
+            .flatten()
+            .filter_map(|file| fs::canonicalize(file).ok())
+            .collect()
+    }
+
+    /// Runs the commands for changes, unless they're to be left alone.
+    fn on_changes(&self, ops: &[PathOp]) -> Result<bool> {
+        let changed;
+        let ops = match self.content_changed(ops) {
+            Some(filtered) if filtered.is_empty() => {
+                debug!("Files were written with the same contents, ignoring changes");
+                return Ok(true);
+            }
+            Some(filtered) => {
+                changed = filtered;
+                changed.as_slice()
+            }
+            None => ops,
+        };
+
+        // the env files are polled, and reloaded with a rerun from there
+        let env_files = self.env_files();
+        if !env_files.is_empty() && ops.iter().all(|op| env_files.contains(&op.path)) {
+            debug!("Only env files changed, leaving them to be reloaded");
+            return Ok(true);
+        }
+
+        // but changes to them along with other files should be used right away
+        if ops.iter().any(|op| env_files.contains(&op.path)) {
+            match self.reload_env() {
+                // which may change what the build scripts see, so everything runs
+                Ok(Some(declared)) if !declared.is_empty() => {
+                    info!(
+                        "Build scripts depend on {}, which changed, running all commands",
+                        declared.join(", ")
+                    );
+                    self.reporter.emit(&Event::Changed {
+                        paths: ops.iter().map(|op| op.path.as_path()).collect(),
+                    });
+                    return self.run(self.steps.clone(), ops);
+                }
+                Ok(_) => {}
+                Err(err) => error!(
+                    "Failed to reload the env files, keeping the previous values: {}",
+                    err
+                ),
+            }
+        }
+
+        let relevant;
+        let ops = match self.in_build(ops) {
+            Some(filtered) if filtered.is_empty() => {
+                debug!("No changed file is part of the build, ignoring changes");
+                return Ok(true);
+            }
+            Some(filtered) => {
+                relevant = filtered;
+                relevant.as_slice()
+            }
+            None => ops,
+        };
+
+        self.reporter.emit(&Event::Changed {
+            paths: ops.iter().map(|op| op.path.as_path()).collect(),
+        });
+
+        let packages = match self.affected(ops) {
+            Some(packages) => {
+                info!("Affected packages: {}", packages.join(", "));
+                Packages::Affected(packages)
+            }
+            None => Packages::from_matches(&self.matches)?,
+        };
+
+        // commands for matching --on patterns replace the others
+        let paths: Vec<&Path> = ops.iter().map(|op| op.path.as_path()).collect();
+        let routed = self.routes.matching(&paths);
+        let steps = if routed.is_empty() {
+            options::get_steps(&self.matches, &packages)?
+        } else {
+            info!("Matching --on commands: {}", routed.join(", "));
+            options::route_steps(&self.matches, &packages, &routed)
+        };
+
+        if steps.is_empty() {
+            debug!("No --on pattern matches the changes");
+            return Ok(true);
+        }
+
+        self.run(steps, ops)
+    }
+
+    /// Variables describing the run and what changed, for the commands.
+    fn run_env(&self, run: u64, ops: &[PathOp]) -> Vec<(String, OsString)> {
+        let mut vars = vec![(
+            String::from("CARGO_WATCH_RUN_NUMBER"),
+            run.to_string().into(),
+        )];
+        if let Some(code) = self
+            .last_run()
+            .and_then(|outcome| outcome.status)
+            .and_then(|status| status.code())
+        {
+            vars.push(("CARGO_WATCH_PREVIOUS_EXIT".into(), code.to_string().into()));
+        }
+
+        if let Some(file) = self.matches.value_of("changed-paths-file") {
+            match write_paths_file(Path::new(file), ops) {
+                Ok(path) => vars.push(("CARGO_WATCH_PATHS_FILE".into(), path.into())),
+                Err(err) => warn!("Failed to write the changed paths to {}: {}", file, err),
+            }
+        }
+
+        if ops.is_empty() {
+            return vars;
+        }
+
+        vars.extend(changes_env(ops, "CARGO_WATCH_{}_PATHS"));
+        if let Some(all) = join_paths("CARGO_WATCH_CHANGED_PATHS", ops.iter().map(|op| &op.path)) {
+            vars.push(("CARGO_WATCH_CHANGED_PATHS".into(), all));
+        }
+        if !self.args.no_environment {
+            vars.extend(watchexec_env(ops));
+        }
+
+        vars
+    }
+
+    /// Starts the commands, dealing with ones still running as configured.
+    fn run(&self, steps: Vec<Step>, ops: &[PathOp]) -> Result<bool> {
+        let _starting = self.starting.lock().unwrap();
+        if self.process.is_running() {
+            match self.args.on_busy_update {
+                OnBusyUpdate::DoNothing => {
+                    debug!("Command still running, ignoring changes");
+                    return Ok(true);
+                }
+                OnBusyUpdate::Queue => {
+                    debug!("Command still running, waiting for it to finish");
+                    self.process.wait();
+                }
+                _ => {
+                    debug!("Command still running, restarting it");
+                    self.process.stop()?;
+                }
+            }
+        } else {
+            // clean up after the previous run
+            self.process.wait();
+        }
+
+        self.start(steps, ops);
+        if self.once {
+            self.process.wait();
+            return Ok(false);
+        }
+
+        Ok(true)
+    }
+
+    /// Starts the commands in the background. Any previous run should have been
+    /// waited on or stopped.
+    fn start(&self, steps: Vec<Step>, ops: &[PathOp]) {
+        if self.args.clear_screen {
+            self.clear_screen();
+        }
+
+        let run = self.runs.fetch_add(1, Ordering::SeqCst) + 1;
+        let changes = self.run_env(run, ops);
+        let env = Arc::clone(&self.env.lock().unwrap());
+        let reporter = Arc::clone(&self.reporter);
+        let last_run = Arc::clone(&self.last_run);
+        let build_scripts = self.build_scripts.lock().unwrap().clone();
+        let declared = Arc::clone(&self.declared);
+        let declared_changed = Arc::clone(&self.declared_changed);
+        self.process.start(move |supervisor, generation| {
+            let outcome = pipeline::run(
+                supervisor, generation, run, &steps, &env, &changes, &reporter,
+            );
+            if let Some(outcome) = outcome {
+                *last_run.lock().unwrap() = Some(outcome);
+            }
+
+            // build scripts which ran may depend on paths which aren't watched
+            // yet, like on the first build, so watching starts again with them
+            if let Some(scripts) = build_scripts {
+                let now = scripts.declared();
+                let mut known = declared.lock().unwrap();
+                if known.paths != now.paths {
+                    debug!("Build scripts declared other paths: {:?}", now.paths);
+                    declared_changed.store(true, Ordering::SeqCst);
+                    waker::wake();
+                }
+                *known = now;
+            }
+        });
+    }
+}
+
+/// Whether changes to the file can change the local packages: a manifest, a
+/// lockfile, or cargo's config.
+fn is_manifest(path: &Path) -> bool {
+    match path.file_name().and_then(|name| name.to_str()) {
+        Some("Cargo.toml" | "Cargo.lock") => true,
+        Some("config.toml" | "config") => path
+            .parent()
+            .and_then(|dir| dir.file_name())
+            .is_some_and(|dir| dir == ".cargo"),
+        _ => false,
+    }
+}
+
+fn describe_paths<'p>(paths: impl Iterator<Item = &'p PathBuf>) -> String {
+    paths
+        .map(|path| path.display().to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// How often to check whether a git operation is over.
+const GIT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Sends a desktop notification when a run finishes.
+pub struct Notifier;
+
+impl RunObserver for Notifier {
+    fn on_run_finish(&self, outcome: &Outcome) {
+        send_notification(outcome);
+    }
+}
+
+fn send_notification(outcome: &Outcome) {
+    let mut body = match outcome.status {
+        Some(status) => format!("Finished running. Exit status: {}", describe(status)),
+        None => String::from("Finished running. Failed to start"),
+    };
+    if let Some(summary) = &outcome.diagnostics {
+        body.push_str(&format!(". {summary}"));
+    }
+
+    notify_rust::Notification::new()
+        .summary("Cargo Watch finished running")
+        .body(&body)
+        .show()
+        .map(drop)
+        .unwrap_or_else(|err| {
+            log::warn!("Failed to send desktop notification: {}", err);
+        });
+}
+
+/// The longest a list of paths given in a variable can be. Longer lists are
+/// left out, as they might not fit, and are best read from
+/// `--changed-paths-file` instead.
+const MAX_PATHS_LEN: usize = 64 * 1024;
+
+/// Environment variables listing the changed paths by kind of change, named
+/// from the pattern with the kind in place of `{}`.
+fn changes_env(ops: &[PathOp], pattern: &str) -> Vec<(String, OsString)> {
+    let mut by_kind: BTreeMap<&str, Vec<&Path>> = BTreeMap::new();
+    for op in ops {
+        let kind = match op.op {
+            Some(o) if PathOp::is_create(o) => "CREATED",
+            Some(o) if PathOp::is_remove(o) => "REMOVED",
+            Some(o) if PathOp::is_rename(o) => "RENAMED",
+            Some(o) if PathOp::is_write(o) => "WRITTEN",
+            Some(o) if PathOp::is_meta(o) => "META_CHANGED",
+            _ => "OTHERWISE_CHANGED",
+        };
+        by_kind.entry(kind).or_default().push(&op.path);
+    }
+
+    by_kind
+        .into_iter()
+        .filter_map(|(kind, paths)| {
+            let name = pattern.replace("{}", kind);
+            join_paths(&name, paths).map(|joined| (name, joined))
+        })
+        .collect()
+}
+
+/// The `WATCHEXEC_*_PATH` variables as watchexec sets them: with more than one
+/// path, the longest path they have in common is in `WATCHEXEC_COMMON_PATH`,
+/// and taken off the front of the others.
+fn watchexec_env(ops: &[PathOp]) -> Vec<(String, OsString)> {
+    let mut unique: Vec<&Path> = ops.iter().map(|op| op.path.as_path()).collect();
+    unique.sort();
+    unique.dedup();
+
+    let common = match unique.as_slice() {
+        [_, _, ..] => common_path(&unique),
+        _ => None,
+    };
+    let common = match common {
+        Some(common) => common,
+        None => return changes_env(ops, "WATCHEXEC_{}_PATH"),
+    };
+
+    let trimmed: Vec<PathOp> = ops
+        .iter()
+        .map(|op| {
+            let rest = op.path.strip_prefix(&common).unwrap_or(&op.path);
+            PathOp {
+                path: Path::new(MAIN_SEPARATOR_STR).join(rest),
+                ..op.clone()
+            }
+        })
+        .collect();
+
+    let mut vars = vec![("WATCHEXEC_COMMON_PATH".into(), common.into_os_string())];
+    vars.extend(changes_env(&trimmed, "WATCHEXEC_{}_PATH"));
+    vars
+}
+
+/// The longest path which all the paths start with.
+fn common_path(paths: &[&Path]) -> Option<PathBuf> {
+    let (first, rest) = paths.split_first()?;
+    let mut common: Vec<Component> = first.components().collect();
+    for path in rest {
+        let shared = path
+            .components()
+            .zip(&common)
+            .take_while(|(a, b)| a == *b)
+            .count();
+        common.truncate(shared);
+    }
+
+    if common.is_empty() {
+        None
+    } else {
+        Some(common.iter().collect())
+    }
+}
+
+/// Joins paths with the platform's separator, as in `PATH`, unless there are too many.
+fn join_paths<P: AsRef<OsStr>>(name: &str, paths: impl IntoIterator<Item = P>) -> Option<OsString> {
+    let joined = env::join_paths(paths).ok()?;
+    if joined.len() > MAX_PATHS_LEN {
+        info!("Too many changed paths to set {}", name);
+        return None;
+    }
+
+    Some(joined)
+}
+
+/// Writes the changed paths to the file, one per line, and returns its full path.
+fn write_paths_file(file: &Path, ops: &[PathOp]) -> io::Result<PathBuf> {
+    let mut out = BufWriter::new(File::create(file)?);
+    for op in ops {
+        writeln!(out, "{}", op.path.display())?;
+    }
+    out.flush()?;
+
+    fs::canonicalize(file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ops(paths: &[&str]) -> Vec<PathOp> {
+        paths
+            .iter()
+            .map(|path| PathOp::new(Path::new(path), None, None))
+            .collect()
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn recognises_manifests() {
+        assert!(is_manifest(Path::new("/ws/Cargo.toml")));
+        assert!(is_manifest(Path::new("/ws/crates/foo/Cargo.toml")));
+        assert!(is_manifest(Path::new("/ws/Cargo.lock")));
+        assert!(is_manifest(Path::new("/ws/.cargo/config.toml")));
+        assert!(is_manifest(Path::new("/ws/.cargo/config")));
+        assert!(!is_manifest(Path::new("/ws/config.toml")));
+        assert!(!is_manifest(Path::new("/ws/config")));
+        assert!(!is_manifest(Path::new("/ws/.cargo/credentials.toml")));
+        assert!(!is_manifest(Path::new("/ws/src/Cargo.toml.rs")));
+    }
+
+    #[test]
+    fn finds_common_path() {
+        let paths = [Path::new("/ws/a/src/lib.rs"), Path::new("/ws/a/Cargo.toml")];
+        assert_eq!(common_path(&paths), Some(PathBuf::from("/ws/a")));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn sets_watchexec_common_path() {
+        let vars = watchexec_env(&ops(&["/ws/a/src/lib.rs", "/ws/a/Cargo.toml"]));
+        assert_eq!(
+            vars,
+            [
+                ("WATCHEXEC_COMMON_PATH".into(), "/ws/a".into()),
+                (
+                    "WATCHEXEC_OTHERWISE_CHANGED_PATH".into(),
+                    "/src/lib.rs:/Cargo.toml".into()
+                ),
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn leaves_single_watchexec_path_whole() {
+        let vars = watchexec_env(&ops(&["/ws/a/src/lib.rs", "/ws/a/src/lib.rs"]));
+        assert_eq!(
+            vars,
+            [(
+                "WATCHEXEC_OTHERWISE_CHANGED_PATH".into(),
+                "/ws/a/src/lib.rs:/ws/a/src/lib.rs".into()
+            )]
+        );
     }
 }
@@ -0,0 +1,183 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+use cargo_metadata::{Metadata, PackageId};
+use log::debug;
+
+/// A local package: a workspace member or a path dependency.
+#[derive(Clone, Debug)]
+struct LocalPackage {
+    name: String,
+    dir: PathBuf,
+    member: bool,
+}
+
+/// The local packages of the workspace and how they depend on each other,
+/// for working out which members a change affects.
+#[derive(Clone, Debug)]
+pub struct Workspace {
+    packages: HashMap<PackageId, LocalPackage>,
+    /// For each local package, the local packages which depend on it.
+    dependents: HashMap<PackageId, Vec<PackageId>>,
+}
+
+impl Workspace {
+    pub fn from_metadata(metadata: &Metadata) -> Self {
+        let members: HashSet<&PackageId> = metadata.workspace_members.iter().collect();
+        let packages: HashMap<PackageId, LocalPackage> = metadata
+            .packages
+            .iter()
+            // Remote packages have a source. Skip!
+            .filter(|pkg| pkg.source.is_none())
+            .map(|pkg| {
+                // This is a path to Cargo.toml, we want the directory it's in.
+                let mut dir = pkg.manifest_path.clone();
+                dir.pop();
+
+                (
+                    pkg.id.clone(),
+                    LocalPackage {
+                        name: pkg.name.clone(),
+                        dir: dir.into_std_path_buf(),
+                        member: members.contains(&pkg.id),
+                    },
+                )
+            })
+            .collect();
+
+        let mut dependents: HashMap<PackageId, Vec<PackageId>> = HashMap::new();
+        for node in metadata.resolve.iter().flat_map(|r| r.nodes.iter()) {
+            if !packages.contains_key(&node.id) {
+                continue;
+            }
+
+            for dep in &node.deps {
+                if packages.contains_key(&dep.pkg) {
+                    dependents
+                        .entry(dep.pkg.clone())
+                        .or_default()
+                        .push(node.id.clone());
+                }
+            }
+        }
+
+        Self {
+            packages,
+            dependents,
+        }
+    }
+
+    /// The local package whose directory contains the path, preferring the innermost.
+    fn owner(&self, path: &Path) -> Option<&PackageId> {
+        self.packages
+            .iter()
+            .filter(|(_, pkg)| path.starts_with(&pkg.dir))
+            .max_by_key(|(_, pkg)| pkg.dir.components().count())
+            .map(|(id, _)| id)
+    }
+
+    /// Names of the workspace members affected by changes to these paths: the
+    /// members which own them, and all members which depend on those.
+    ///
+    /// Returns `None` if a path is outside of all local packages, as then
+    /// anything could be affected.
+    pub fn affected<'p>(&self, paths: impl IntoIterator<Item = &'p Path>) -> Option<Vec<String>> {
+        let mut to_check = Vec::new();
+        for path in paths {
+            match self.owner(path) {
+                Some(id) => to_check.push(id.clone()),
+                None => {
+                    debug!("Change to {:?} is outside all local packages", path);
+                    return None;
+                }
+            }
+        }
+
+        let mut seen = HashSet::new();
+        while let Some(id) = to_check.pop() {
+            if !seen.insert(id.clone()) {
+                continue;
+            }
+
+            if let Some(dependents) = self.dependents.get(&id) {
+                to_check.extend(dependents.iter().cloned());
+            }
+        }
+
+        let mut names: Vec<String> = seen
+            .iter()
+            .filter_map(|id| self.packages.get(id))
+            .filter(|pkg| pkg.member)
+            .map(|pkg| pkg.name.clone())
+            .collect();
+        names.sort();
+        names.dedup();
+        Some(names)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn id(name: &str) -> PackageId {
+        PackageId { repr: name.into() }
+    }
+
+    /// `app` depends on `core`, and so does `tool`, which is a path
+    /// dependency outside of the workspace.
+    fn workspace() -> Workspace {
+        let package = |name: &str, dir: &str, member| {
+            (
+                id(name),
+                LocalPackage {
+                    name: name.into(),
+                    dir: dir.into(),
+                    member,
+                },
+            )
+        };
+
+        Workspace {
+            packages: HashMap::from([
+                package("root", "/ws", true),
+                package("core", "/ws/core", true),
+                package("app", "/ws/app", true),
+                package("tool", "/tool", false),
+            ]),
+            dependents: HashMap::from([(id("core"), vec![id("app"), id("tool")])]),
+        }
+    }
+
+    #[test]
+    fn affects_owner_and_dependents() {
+        let paths = [Path::new("/ws/core/src/lib.rs")];
+        assert_eq!(
+            workspace().affected(paths),
+            Some(vec!["app".into(), "core".into()])
+        );
+    }
+
+    #[test]
+    fn prefers_innermost_owner() {
+        let paths = [Path::new("/ws/app/src/main.rs"), Path::new("/ws/build.rs")];
+        assert_eq!(
+            workspace().affected(paths),
+            Some(vec!["app".into(), "root".into()])
+        );
+    }
+
+    #[test]
+    fn leaves_out_non_members() {
+        let paths = [Path::new("/tool/src/lib.rs")];
+        assert_eq!(workspace().affected(paths), Some(Vec::new()));
+    }
+
+    #[test]
+    fn affects_everything_outside_packages() {
+        let paths = [Path::new("/ws/core/src/lib.rs"), Path::new("/elsewhere/x")];
+        assert_eq!(workspace().affected(paths), None);
+    }
+}
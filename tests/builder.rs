@@ -0,0 +1,99 @@
+use std::{
+    sync::{
+        mpsc::{self, Sender},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+use cargo_watch::{Builder, Outcome, RunObserver};
+
+#[test]
+fn builds_with_defaults() {
+    Builder::new()
+        .cargo("check")
+        .ignore("*.tmp")
+        .quiet(true)
+        .build()
+        .unwrap();
+}
+
+#[test]
+fn rejects_conflicting_options() {
+    assert!(Builder::new()
+        .package("cargo-watch")
+        .all(true)
+        .build()
+        .is_err());
+}
+
+#[test]
+fn rejects_bad_patterns() {
+    assert!(Builder::new().on("[bad", "echo bad").build().is_err());
+}
+
+struct Recorder(Mutex<Sender<String>>);
+
+impl RunObserver for Recorder {
+    fn on_run_start(&self, run: u64, commands: &[&str]) {
+        self.send(format!("start {run} {}", commands.join(", ")));
+    }
+
+    fn on_command_start(&self, _run: u64, command: &str) {
+        self.send(format!("command {command}"));
+    }
+
+    fn on_command_finish(
+        &self,
+        _run: u64,
+        command: &str,
+        status: Option<std::process::ExitStatus>,
+        _duration: Duration,
+    ) {
+        self.send(format!(
+            "finished {command} {:?}",
+            status.and_then(|s| s.code())
+        ));
+    }
+
+    fn on_run_finish(&self, outcome: &Outcome) {
+        self.send(format!(
+            "done {} {:?}",
+            outcome.run,
+            outcome.status.and_then(|s| s.code())
+        ));
+    }
+}
+
+impl Recorder {
+    fn send(&self, event: String) {
+        self.0.lock().unwrap().send(event).ok();
+    }
+}
+
+#[test]
+fn tells_observers_about_runs() {
+    let (tx, rx) = mpsc::channel();
+    let handler = Builder::new()
+        .command("echo one")
+        .command("exit 2")
+        .quiet(true)
+        .observer(Arc::new(Recorder(Mutex::new(tx))))
+        .build()
+        .unwrap();
+    thread::spawn(move || handler.watch());
+
+    let events: Vec<String> = rx.iter().take(6).collect();
+    assert_eq!(
+        events,
+        [
+            "start 1 echo one, exit 2",
+            "command echo one",
+            "finished echo one Some(0)",
+            "command exit 2",
+            "finished exit 2 Some(2)",
+            "done 1 Some(2)",
+        ]
+    );
+}
@@ -0,0 +1,151 @@
+use assert_cmd::assert::Assert;
+use assert_cmd::prelude::*;
+use predicates::{prelude::*, str::contains};
+use std::{
+    fs::OpenOptions,
+    io::{self, Write},
+    process::{Command, Stdio},
+    thread::sleep,
+    time::{Duration, Instant},
+};
+use wait_timeout::ChildExt;
+
+fn touch(name: &str) -> io::Result<()> {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(true)
+        .open(format!("./tests/touchdata/config/{}.txt", name))?;
+
+    writeln!(&mut file, "{:?}", Instant::now())?;
+    Ok(())
+}
+
+/// Runs cargo-watch once in the config fixture, after touching the named file.
+fn watch_config(mut command: Command, name: &str) -> Assert {
+    let mut main = command
+        .stderr(Stdio::piped())
+        .stdout(Stdio::piped())
+        .args(["--testing-only--once", "-C", "./tests/touchdata/config/"])
+        .spawn()
+        .unwrap();
+
+    sleep(Duration::from_secs(2));
+    touch(name).unwrap();
+
+    if main
+        .wait_timeout(Duration::from_secs(30))
+        .unwrap()
+        .is_none()
+    {
+        main.kill().unwrap();
+    }
+
+    main.wait_with_output().unwrap().assert()
+}
+
+#[test]
+fn from_config_file() {
+    let command = Command::cargo_bin("cargo-watch").unwrap();
+
+    watch_config(command, "from-file")
+        .success()
+        .stdout(contains("[Running 'echo from config']"));
+}
+
+#[test]
+fn cli_overrides_config_file() {
+    let mut command = Command::cargo_bin("cargo-watch").unwrap();
+    command.args(["-s", "echo from cli"]);
+
+    watch_config(command, "from-cli")
+        .success()
+        .stdout(contains("[Running 'echo from cli']"));
+}
+
+#[test]
+fn with_profile() {
+    let mut command = Command::cargo_bin("cargo-watch").unwrap();
+    command.args(["--profile", "other"]);
+
+    watch_config(command, "with-profile")
+        .success()
+        .stdout(contains("[Running 'echo from profile']"));
+}
+
+#[test]
+fn profile_command_replaces_base_command() {
+    let mut command = Command::cargo_bin("cargo-watch").unwrap();
+    command.args(["--profile", "cargo"]);
+
+    watch_config(command, "profile-command")
+        .success()
+        .stdout(contains("[Running 'cargo --version']"))
+        .stdout(contains("from config").not());
+}
+
+#[test]
+fn env_overrides_config_file() {
+    let mut command = Command::cargo_bin("cargo-watch").unwrap();
+    command.env("CARGO_WATCH_SHELL", "echo from env");
+
+    watch_config(command, "from-env")
+        .success()
+        .stdout(contains("[Running 'echo from env']"));
+}
+
+#[test]
+fn cli_overrides_env() {
+    let mut command = Command::cargo_bin("cargo-watch").unwrap();
+    command
+        .env("CARGO_WATCH_SHELL", "echo from env")
+        .args(["-s", "echo from cli"]);
+
+    watch_config(command, "env-from-cli")
+        .success()
+        .stdout(contains("[Running 'echo from cli']"));
+}
+
+#[test]
+fn rejects_bad_env_flag() {
+    Command::cargo_bin("cargo-watch")
+        .unwrap()
+        .env("CARGO_WATCH_POLL", "maybe")
+        .args(["--testing-only--once"])
+        .assert()
+        .failure()
+        .stderr(contains("CARGO_WATCH_POLL"));
+}
+
+#[test]
+fn rejects_nested_profile() {
+    let dir = std::env::temp_dir().join(format!("cargo-watch-nested-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(
+        dir.join(".cargo-watch.toml"),
+        "[profile.outer]\nshell = \"echo outer\"\n\n[profile.outer.profile.inner]\nshell = \"echo inner\"\n",
+    )
+    .unwrap();
+
+    Command::cargo_bin("cargo-watch")
+        .unwrap()
+        .args(["--testing-only--once", "-C"])
+        .arg(&dir)
+        .assert()
+        .failure()
+        .stderr(contains("Profile 'outer'"));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn env_list_from_env() {
+    let mut command = Command::cargo_bin("cargo-watch").unwrap();
+    command
+        .env("CARGO_WATCH_ENV", "FIRST=one;SECOND=two\nTHIRD=three")
+        .args(["-s", "echo \"env $FIRST $SECOND $THIRD\""]);
+
+    watch_config(command, "env-list")
+        .success()
+        .stdout(contains("env one two three"));
+}
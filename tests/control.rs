@@ -0,0 +1,94 @@
+#![cfg(unix)]
+
+use assert_cmd::prelude::*;
+use serde_json::Value;
+use std::{
+    io::{BufRead, BufReader, Write},
+    os::unix::net::UnixStream,
+    path::PathBuf,
+    process::{Command, Stdio},
+    thread::sleep,
+    time::{Duration, Instant},
+};
+use wait_timeout::ChildExt;
+
+fn send(stream: &mut UnixStream, reader: &mut impl BufRead, command: &str) -> Value {
+    writeln!(stream, "{}", command).unwrap();
+    let mut line = String::new();
+    reader.read_line(&mut line).unwrap();
+    serde_json::from_str(&line).unwrap()
+}
+
+#[test]
+fn with_control() {
+    let mut main = Command::cargo_bin("cargo-watch")
+        .unwrap()
+        .stdin(Stdio::null())
+        .stderr(Stdio::piped())
+        .stdout(Stdio::null())
+        .args([
+            "--no-vcs-ignores",
+            "--no-keys",
+            "--control",
+            "-w",
+            "./tests/touchdata/",
+            "-s",
+            "echo it runs",
+        ])
+        .spawn()
+        .unwrap();
+
+    let mut stderr = BufReader::new(main.stderr.take().unwrap());
+    let path = loop {
+        let mut line = String::new();
+        assert_ne!(stderr.read_line(&mut line).unwrap(), 0, "no socket");
+        if let Some(path) = line.trim().strip_prefix("[Listening for commands on ") {
+            break PathBuf::from(path.trim_end_matches(']'));
+        }
+    };
+
+    let mut stream = UnixStream::connect(&path).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(10)))
+        .unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+    let status = send(&mut stream, &mut reader, "status");
+    assert_eq!(status["ok"], true);
+    assert_eq!(status["paused"], false);
+
+    assert_eq!(send(&mut stream, &mut reader, "pause")["ok"], true);
+    assert_eq!(send(&mut stream, &mut reader, "status")["paused"], true);
+    assert_eq!(send(&mut stream, &mut reader, "resume")["ok"], true);
+    assert_eq!(send(&mut stream, &mut reader, "status")["paused"], false);
+
+    assert_eq!(send(&mut stream, &mut reader, "rerun")["ok"], true);
+    let started = Instant::now();
+    loop {
+        let status = send(&mut stream, &mut reader, "status");
+        if status["last-run"]["run"] == 2 {
+            assert_eq!(status["last-run"]["success"], true);
+            break;
+        }
+        assert!(started.elapsed() < Duration::from_secs(30), "no rerun");
+        sleep(Duration::from_millis(100));
+    }
+
+    let unknown = send(&mut stream, &mut reader, "bogus");
+    assert_eq!(unknown["ok"], false);
+    assert_eq!(unknown["error"], "unknown command 'bogus'");
+
+    // SAFETY: the pid is of the child, which hasn't been waited on
+    unsafe {
+        libc::kill(main.id() as libc::pid_t, libc::SIGTERM);
+    }
+    if main
+        .wait_timeout(Duration::from_secs(30))
+        .unwrap()
+        .is_none()
+    {
+        main.kill().unwrap();
+    }
+
+    assert!(!path.exists(), "socket left behind");
+}
@@ -11,7 +11,11 @@ use wait_timeout::ChildExt;
 
 fn touch(n: u8) -> io::Result<()> {
     let path: PathBuf = format!("./tests/touchdata/{}.txt", n).into();
-    let mut file = OpenOptions::new().create(true).write(true).open(path)?;
+    let mut file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(true)
+        .open(path)?;
 
     writeln!(&mut file, "{:?}", Instant::now())?;
     Ok(())
@@ -35,7 +39,7 @@ fn without_poll() {
         .unwrap()
         .stderr(Stdio::piped())
         .stdout(Stdio::piped())
-        .args(&[
+        .args([
             "--testing-only--once",
             "--no-vcs-ignores",
             "-w",
@@ -66,7 +70,7 @@ fn with_poll() {
         .unwrap()
         .stderr(Stdio::piped())
         .stdout(Stdio::piped())
-        .args(&[
+        .args([
             "--testing-only--once",
             "--no-vcs-ignores",
             "--poll",
@@ -99,7 +103,7 @@ fn with_announce() {
         .unwrap()
         .stderr(Stdio::piped())
         .stdout(Stdio::piped())
-        .args(&[
+        .args([
             "--testing-only--once",
             "--no-vcs-ignores",
             "--poll",
@@ -132,7 +136,7 @@ fn without_announce() {
         .unwrap()
         .stderr(Stdio::piped())
         .stdout(Stdio::piped())
-        .args(&[
+        .args([
             "--testing-only--once",
             "--no-vcs-ignores",
             "--quiet",
@@ -167,7 +171,7 @@ fn with_error() {
         .unwrap()
         .stderr(Stdio::piped())
         .stdout(Stdio::piped())
-        .args(&[
+        .args([
             "--testing-only--once",
             "--no-vcs-ignores",
             "--poll",
@@ -193,5 +197,331 @@ fn with_error() {
     }
 
     insta::assert_snapshot!("with_error.stderr", std_to_string(&mut main.stderr));
-    insta::assert_snapshot!("with_error.stdout", std_to_string(&mut main.stdout));
+    insta::with_settings!({filters => vec![(r"in \d+\.\d+s", "in [duration]")]}, {
+        insta::assert_snapshot!("with_error.stdout", std_to_string(&mut main.stdout));
+    });
+}
+
+#[cfg(unix)]
+#[test]
+fn with_json_events() {
+    let mut main = Command::cargo_bin("cargo-watch")
+        .unwrap()
+        .stderr(Stdio::piped())
+        .stdout(Stdio::piped())
+        .args([
+            "--testing-only--once",
+            "--no-vcs-ignores",
+            "--poll",
+            "--message-format",
+            "json",
+            "-w",
+            "./tests/touchdata/",
+            "-s",
+            "echo with json",
+            "-s",
+            "false",
+        ])
+        .spawn()
+        .unwrap();
+
+    sleep(Duration::from_secs(2));
+    touch(5).unwrap();
+
+    if main
+        .wait_timeout(Duration::from_secs(30))
+        .unwrap()
+        .is_none()
+    {
+        main.kill().unwrap();
+    }
+
+    let stdout = std_to_string(&mut main.stdout);
+    let events: Vec<serde_json::Value> = stdout
+        .lines()
+        .filter(|line| line.starts_with('{'))
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    let kinds: Vec<&str> = events
+        .iter()
+        .map(|event| event["event"].as_str().unwrap())
+        .collect();
+    assert_eq!(
+        kinds,
+        [
+            "changed",
+            "run-started",
+            "command-started",
+            "command-finished",
+            "command-started",
+            "command-finished",
+            "run-finished"
+        ]
+    );
+
+    let finished = events.last().unwrap();
+    assert_eq!(finished["success"], false);
+    assert_eq!(finished["exit-status"], 1);
+    assert_eq!(finished["failed-command"], "false");
+    assert!(stdout.contains("with json\n"));
+}
+
+#[test]
+#[cfg(unix)]
+fn with_scoped_env() {
+    let mut main = Command::cargo_bin("cargo-watch")
+        .unwrap()
+        .stderr(Stdio::piped())
+        .stdout(Stdio::piped())
+        .args([
+            "--testing-only--once",
+            "--no-vcs-ignores",
+            "--poll",
+            "-w",
+            "./tests/touchdata/",
+            "-E",
+            "WHO=everyone",
+            "-E",
+            "echo:ONLY=echo",
+            "-s",
+            "echo \"$WHO $ONLY\"",
+            "-s",
+            "printf '%s %s\\n' \"$WHO\" \"${ONLY:-unset}\"",
+        ])
+        .spawn()
+        .unwrap();
+
+    sleep(Duration::from_secs(2));
+    touch(6).unwrap();
+
+    if main
+        .wait_timeout(Duration::from_secs(30))
+        .unwrap()
+        .is_none()
+    {
+        main.kill().unwrap();
+    }
+
+    let stdout = std_to_string(&mut main.stdout);
+    assert!(stdout.contains("everyone echo\n"));
+    assert!(stdout.contains("everyone unset\n"));
+}
+
+#[test]
+#[cfg(unix)]
+fn with_env_file_reload() {
+    let env_file = "./tests/touchdata/reload.env";
+    std::fs::write(env_file, "GREETING=before\n").unwrap();
+
+    let mut main = Command::cargo_bin("cargo-watch")
+        .unwrap()
+        .stderr(Stdio::piped())
+        .stdout(Stdio::piped())
+        .args([
+            "--no-vcs-ignores",
+            "--no-keys",
+            "-w",
+            "./tests/touchdata/",
+            "-i",
+            "*.env",
+            "--env-file",
+            env_file,
+            "-s",
+            "echo \"greeting $GREETING\"",
+        ])
+        .spawn()
+        .unwrap();
+
+    sleep(Duration::from_secs(2));
+    std::fs::write(env_file, "GREETING=after\n").unwrap();
+    sleep(Duration::from_secs(3));
+
+    main.kill().unwrap();
+    main.wait().unwrap();
+    let stdout = std_to_string(&mut main.stdout);
+    assert!(stdout.contains("greeting before\n"));
+    assert!(stdout.contains("greeting after\n"));
+}
+
+#[test]
+#[cfg(unix)]
+fn with_run_env() {
+    let mut main = Command::cargo_bin("cargo-watch")
+        .unwrap()
+        .stderr(Stdio::piped())
+        .stdout(Stdio::piped())
+        .args([
+            "--testing-only--once",
+            "--no-vcs-ignores",
+            "--poll",
+            "-w",
+            "./tests/touchdata/",
+            "-s",
+            "echo \"run $CARGO_WATCH_RUN_NUMBER changed $CARGO_WATCH_CHANGED_PATHS\"",
+        ])
+        .spawn()
+        .unwrap();
+
+    sleep(Duration::from_secs(2));
+    touch(7).unwrap();
+
+    if main
+        .wait_timeout(Duration::from_secs(30))
+        .unwrap()
+        .is_none()
+    {
+        main.kill().unwrap();
+    }
+
+    let stdout = std_to_string(&mut main.stdout);
+    assert!(stdout.contains("run 1 changed "), "{stdout}");
+    assert!(stdout.contains("7.txt"), "{stdout}");
+}
+
+#[test]
+#[cfg(unix)]
+fn with_paths_file() {
+    let file = std::env::temp_dir().join("cargo-watch-changed-paths.txt");
+    let mut main = Command::cargo_bin("cargo-watch")
+        .unwrap()
+        .stderr(Stdio::piped())
+        .stdout(Stdio::piped())
+        .args([
+            "--testing-only--once",
+            "--no-vcs-ignores",
+            "-w",
+            "./tests/touchdata/",
+            "--changed-paths-file",
+            file.to_str().unwrap(),
+            "-s",
+            "cat \"$CARGO_WATCH_PATHS_FILE\"",
+        ])
+        .spawn()
+        .unwrap();
+
+    sleep(Duration::from_secs(2));
+    touch(11).unwrap();
+
+    if main
+        .wait_timeout(Duration::from_secs(30))
+        .unwrap()
+        .is_none()
+    {
+        main.kill().unwrap();
+    }
+
+    let stdout = std_to_string(&mut main.stdout);
+    assert!(stdout.contains("11.txt\n"), "{stdout}");
+}
+
+#[test]
+#[cfg(unix)]
+fn with_routes() {
+    let mut main = Command::cargo_bin("cargo-watch")
+        .unwrap()
+        .stderr(Stdio::piped())
+        .stdout(Stdio::piped())
+        .args([
+            "--testing-only--once",
+            "--no-vcs-ignores",
+            "--poll",
+            "-w",
+            "./tests/touchdata/",
+            "--on",
+            "*.rs=echo rust changed",
+            "--on",
+            "tests/touchdata/*.txt=echo text changed",
+            "-s",
+            "echo something else changed",
+        ])
+        .spawn()
+        .unwrap();
+
+    sleep(Duration::from_secs(2));
+    touch(8).unwrap();
+
+    if main
+        .wait_timeout(Duration::from_secs(30))
+        .unwrap()
+        .is_none()
+    {
+        main.kill().unwrap();
+    }
+
+    let stdout = std_to_string(&mut main.stdout);
+    assert!(stdout.contains("text changed\n"), "{stdout}");
+    assert!(!stdout.contains("rust changed"), "{stdout}");
+    assert!(!stdout.contains("something else changed"), "{stdout}");
+}
+
+#[test]
+fn with_exts() {
+    let mut main = Command::cargo_bin("cargo-watch")
+        .unwrap()
+        .stderr(Stdio::piped())
+        .stdout(Stdio::piped())
+        .args([
+            "--testing-only--once",
+            "--no-vcs-ignores",
+            "-w",
+            "./tests/touchdata/",
+            "-e",
+            "rs,txt",
+            "-s",
+            "echo filtered",
+        ])
+        .spawn()
+        .unwrap();
+
+    sleep(Duration::from_secs(2));
+    touch(9).unwrap();
+
+    if main
+        .wait_timeout(Duration::from_secs(30))
+        .unwrap()
+        .is_none()
+    {
+        main.kill().unwrap();
+    }
+
+    let stdout = std_to_string(&mut main.stdout);
+    assert!(stdout.contains("filtered\n"), "{stdout}");
+}
+
+#[cfg(not(target_os = "macos"))]
+#[test]
+fn with_skip_unchanged() {
+    std::fs::write("./tests/touchdata/10.txt", "first\n").unwrap();
+    let mut main = Command::cargo_bin("cargo-watch")
+        .unwrap()
+        .stdin(Stdio::null())
+        .stderr(Stdio::piped())
+        .stdout(Stdio::piped())
+        .args([
+            "--postpone",
+            "--no-keys",
+            "--no-vcs-ignores",
+            "-w",
+            "./tests/touchdata/",
+            "--skip-unchanged",
+            "-s",
+            "cat ./tests/touchdata/10.txt",
+        ])
+        .spawn()
+        .unwrap();
+
+    // files are hashed at startup, so even the first change is told apart
+    sleep(Duration::from_secs(2));
+    std::fs::write("./tests/touchdata/10.txt", "first\n").unwrap();
+    sleep(Duration::from_secs(2));
+    std::fs::write("./tests/touchdata/10.txt", "second\n").unwrap();
+    sleep(Duration::from_secs(2));
+    std::fs::write("./tests/touchdata/10.txt", "second\n").unwrap();
+    sleep(Duration::from_secs(2));
+
+    main.kill().unwrap();
+    main.wait().unwrap();
+    let stdout = std_to_string(&mut main.stdout);
+    assert_eq!(stdout.matches("first").count(), 0, "{stdout}");
+    assert_eq!(stdout.matches("second").count(), 1, "{stdout}");
 }
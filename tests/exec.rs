@@ -67,3 +67,32 @@ fn without_watch() {
         .success()
         .stdout(is_match(r"cargo-watch \d+\.\d+\.\d+\n").unwrap());
 }
+
+#[test]
+fn completions() {
+    for shell in ["bash", "elvish", "fish", "powershell", "zsh"] {
+        Command::cargo_bin("cargo-watch")
+            .unwrap()
+            .args(["watch", "completions", shell])
+            .assert()
+            .success()
+            .stdout(is_match("skip-local-deps").unwrap());
+    }
+}
+
+#[test]
+fn completion_values() {
+    Command::cargo_bin("cargo-watch")
+        .unwrap()
+        .args(["watch", "completions", "--values", "packages"])
+        .assert()
+        .success()
+        .stdout("cargo-watch\n");
+
+    Command::cargo_bin("cargo-watch")
+        .unwrap()
+        .args(["watch", "completions", "--values", "bins"])
+        .assert()
+        .success()
+        .stdout("cargo-watch\n");
+}